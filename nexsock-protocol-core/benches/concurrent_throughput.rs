@@ -0,0 +1,86 @@
+use bincode::{Decode, Encode};
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use nexsock_protocol_core::header::Header;
+use nexsock_protocol_core::header::standard::StandardHeaderParser;
+use nexsock_protocol_core::message_flags::MessageFlags;
+use nexsock_protocol_core::traits::MessageBody;
+use nexsock_protocol_core::transport::Transport;
+use std::time::{Duration, Instant};
+use tikv_jemallocator::Jemalloc;
+use tokio::runtime::Runtime;
+
+#[global_allocator]
+static GLOBAL: Jemalloc = Jemalloc;
+
+/// Capacity of the in-memory pipe between the two tasks, roughly a socket buffer.
+const PIPE_CAPACITY: usize = 256 * 1024;
+
+#[derive(Encode, Decode)]
+struct Message {
+    id: u64,
+    data: Vec<u8>,
+}
+
+impl MessageBody for Message {}
+
+/// Sends `count` messages with `payload_len` bytes of data from a writer task to a reader task
+/// over the split halves of a duplex-backed transport, returning how long it took.
+async fn transfer(count: u64, payload_len: usize) -> Duration {
+    let (client, server) = Transport::pair(PIPE_CAPACITY);
+    let (_, mut writer) = client.split();
+    let (mut reader, _) = server.split();
+
+    let header = Header::new(1, 1, MessageFlags::NONE, 0, 0).to_bytes::<StandardHeaderParser>();
+    let data = vec![0xA5; payload_len];
+
+    let start = Instant::now();
+
+    let writing = tokio::spawn(async move {
+        for id in 0..count {
+            let message = Message {
+                id,
+                data: data.clone(),
+            };
+
+            writer
+                .write_message(message.to_frame(header))
+                .await
+                .unwrap();
+        }
+    });
+    let reading = tokio::spawn(async move {
+        for expected in 0..count {
+            let message: Message = reader.read_message().await.unwrap();
+            assert_eq!(message.id, expected);
+        }
+    });
+
+    writing.await.unwrap();
+    reading.await.unwrap();
+
+    start.elapsed()
+}
+
+pub fn concurrent_throughput_benchmark(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("Concurrent Throughput");
+
+    group.measurement_time(Duration::from_secs(5));
+
+    for (name, payload_len) in [("small", 64), ("large", 64 * 1024)] {
+        // One element per message, so criterion reports messages per second.
+        group.throughput(Throughput::Elements(1));
+        group.bench_with_input(
+            BenchmarkId::new("split_duplex", name),
+            &payload_len,
+            |b, &payload_len| {
+                b.iter_custom(|iters| runtime.block_on(transfer(iters, payload_len)));
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, concurrent_throughput_benchmark);
+criterion_main!(benches);
@@ -5,6 +5,8 @@ use nexsock_protocol_core::header::Header;
 use nexsock_protocol_core::header::optimized::OptimizedHeaderParser;
 #[cfg(all(feature = "simd", target_arch = "aarch64"))]
 use nexsock_protocol_core::header::simd::Aarch64NeonHeaderParser;
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+use nexsock_protocol_core::header::simd::X86SimdHeaderParser;
 use nexsock_protocol_core::header::standard::StandardHeaderParser;
 use nexsock_protocol_core::message_flags::MessageFlags;
 use tikv_jemallocator::Jemalloc;
@@ -75,6 +77,13 @@ pub fn header_from_byte_parsing_benchmark(c: &mut Criterion) {
             },
         );
 
+        #[cfg(all(feature = "simd", target_arch = "x86_64", target_feature = "sse2"))]
+        group.bench_with_input(
+            BenchmarkId::new("x86 SSE2", format!("case_{}", i)),
+            &header_bytes,
+            |b, bytes| b.iter(|| black_box(Header::parse::<X86SimdHeaderParser>(black_box(bytes)))),
+        );
+
         // Standard parser
         group.bench_with_input(
             BenchmarkId::new("Standard", format!("case_{}", i)),
@@ -109,6 +118,13 @@ pub fn header_to_byte_conversion_benchmark(c: &mut Criterion) {
             },
         );
 
+        #[cfg(all(feature = "simd", target_arch = "x86_64", target_feature = "sse2"))]
+        group.bench_with_input(
+            BenchmarkId::new("x86 SSE2", format!("case_{}", i)),
+            header,
+            |b, header| b.iter(|| black_box(black_box(header).to_bytes::<X86SimdHeaderParser>())),
+        );
+
         group.bench_with_input(
             BenchmarkId::new("Standard", format!("case_{}", i)),
             header,
@@ -167,6 +183,18 @@ pub fn header_roundtrip_benchmark(c: &mut Criterion) {
             },
         );
 
+        #[cfg(all(feature = "simd", target_arch = "x86_64", target_feature = "sse2"))]
+        group.bench_with_input(
+            BenchmarkId::new("x86 SSE2", format!("case_{}", i)),
+            header,
+            |b, header| {
+                b.iter(|| {
+                    let bytes = black_box(header).to_bytes::<X86SimdHeaderParser>();
+                    black_box(Header::parse::<X86SimdHeaderParser>(black_box(&bytes)))
+                })
+            },
+        );
+
         group.bench_with_input(
             BenchmarkId::new("Optimized + Standard", format!("case_{}", i)),
             header,
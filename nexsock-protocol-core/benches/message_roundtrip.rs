@@ -0,0 +1,94 @@
+use bytes::Bytes;
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use nexsock_protocol_core::frame::Frame;
+use nexsock_protocol_core::header::Header;
+use nexsock_protocol_core::header::standard::StandardHeaderParser;
+use nexsock_protocol_core::message_flags::MessageFlags;
+use nexsock_protocol_core::traits::MessageBody;
+use nexsock_protocol_core::transport::Transport;
+use std::time::{Duration, Instant};
+use tikv_jemallocator::Jemalloc;
+use tokio::runtime::Runtime;
+
+#[global_allocator]
+static GLOBAL: Jemalloc = Jemalloc;
+
+/// Capacity of the in-memory pipe between the two transports, roughly a socket buffer.
+const PIPE_CAPACITY: usize = 256 * 1024;
+
+const PAYLOAD_SIZES: [(&str, usize); 3] = [("64B", 64), ("4KiB", 4 * 1024), ("1MiB", 1024 * 1024)];
+
+/// Sends `iters` messages with `payload_len` bytes of data through `write_message` and reads them
+/// back with `read_message`, so the time includes bincode encoding and decoding.
+async fn roundtrip_message(iters: u64, payload_len: usize) -> Duration {
+    let (mut client, mut server) = Transport::pair(PIPE_CAPACITY);
+
+    let header = Header::new(1, 1, MessageFlags::NONE, 0, 0).to_bytes::<StandardHeaderParser>();
+    let data = vec![0xA5u8; payload_len];
+
+    let start = Instant::now();
+
+    for _ in 0..iters {
+        let (written, read) = tokio::join!(
+            client.write_message(data.clone().to_frame(header)),
+            server.read_message::<Vec<u8>>()
+        );
+
+        written.unwrap();
+        assert_eq!(read.unwrap().len(), payload_len);
+    }
+
+    start.elapsed()
+}
+
+/// Like [`roundtrip_message`], but with an already encoded payload sent through `write_raw` and
+/// read with `read_raw`, so only the framing is measured.
+async fn roundtrip_raw(iters: u64, payload_len: usize) -> Duration {
+    let (mut client, mut server) = Transport::pair(PIPE_CAPACITY);
+
+    let header = Header::new(1, 1, MessageFlags::HAS_PAYLOAD, payload_len as u32, 0);
+    let frame =
+        Frame::from_encoded::<StandardHeaderParser>(header, Bytes::from(vec![0xA5u8; payload_len]));
+
+    let start = Instant::now();
+
+    for _ in 0..iters {
+        let (written, read) = tokio::join!(client.write_raw(&frame), server.read_raw());
+
+        written.unwrap();
+        assert_eq!(read.unwrap().1.len(), payload_len);
+    }
+
+    start.elapsed()
+}
+
+pub fn message_roundtrip_benchmark(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("Message Roundtrip");
+
+    group.measurement_time(Duration::from_secs(5));
+
+    for (name, payload_len) in PAYLOAD_SIZES {
+        // Payload bytes per iteration, so criterion reports bytes per second.
+        group.throughput(Throughput::Bytes(payload_len as u64));
+        group.bench_with_input(
+            BenchmarkId::new("message", name),
+            &payload_len,
+            |b, &payload_len| {
+                b.iter_custom(|iters| runtime.block_on(roundtrip_message(iters, payload_len)));
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("raw", name),
+            &payload_len,
+            |b, &payload_len| {
+                b.iter_custom(|iters| runtime.block_on(roundtrip_raw(iters, payload_len)));
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, message_roundtrip_benchmark);
+criterion_main!(benches);
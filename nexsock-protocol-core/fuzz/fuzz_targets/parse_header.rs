@@ -0,0 +1,25 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use nexsock_protocol_core::constants::HEADER_SIZE;
+use nexsock_protocol_core::header::optimized::OptimizedHeaderParser;
+use nexsock_protocol_core::header::parse_lenient;
+use nexsock_protocol_core::header::runtime::RuntimeHeaderParser;
+use nexsock_protocol_core::header::standard::StandardHeaderParser;
+use nexsock_protocol_core::traits::header::HeaderDeserializer;
+
+fuzz_target!(|data: &[u8]| {
+    let standard = StandardHeaderParser::parse(data);
+    let optimized = OptimizedHeaderParser::parse(data);
+    let runtime = RuntimeHeaderParser::parse(data);
+
+    if data.len() < HEADER_SIZE {
+        assert!(standard.is_none() && optimized.is_none() && runtime.is_none());
+    } else {
+        assert!(standard.is_some());
+        assert_eq!(optimized, standard);
+        assert_eq!(runtime, standard);
+    }
+
+    let _ = parse_lenient(data);
+});
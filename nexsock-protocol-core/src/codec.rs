@@ -0,0 +1,316 @@
+#![cfg(feature = "tokio-codec")]
+
+//! [`tokio_util::codec`] support, for using the protocol with `Framed`, `FramedRead` and
+//! `FramedWrite`.
+
+use crate::constants::HEADER_SIZE;
+use crate::error::{ProtocolError, ProtocolResult};
+use crate::frame::Frame;
+use crate::header::Header;
+use crate::header::standard::StandardHeaderParser;
+use crate::message_flags::MessageFlags;
+use crate::protocol_params::MAGIC;
+use crate::traits::MessageBody;
+use crate::traits::header::HeaderSerializer;
+use crate::transport::{DEFAULT_MAX_PAYLOAD_LEN, checksum, payload_len};
+use bytes::{BufMut, BytesMut};
+use std::io;
+use std::marker::PhantomData;
+use tokio_util::codec::{Decoder, Encoder};
+
+const PREFIX_SIZE: usize = MAGIC.len() + HEADER_SIZE;
+const CHECKSUM_SIZE: usize = 4;
+
+/// Codec for frames in the standard header layout, decoding their bodies as `T`.
+///
+/// Encoding takes a whole [`Frame`] rather than a bare `T`, since the header's id, version, flags
+/// and sequence number have to come from somewhere. The payload length and `HAS_PAYLOAD` are
+/// filled in from the encoded body, like [`Transport::write_message`] does.
+///
+/// Compression and encryption are not supported: such frames fail to decode, and `COMPRESSED` and
+/// `ENCRYPTED` are cleared on encode. Checksum trailers are written and verified as usual.
+///
+/// [`Transport::write_message`]: crate::transport::Transport::write_message
+pub struct NexsockCodec<T> {
+    max_payload_len: u32,
+    _body: PhantomData<fn() -> T>,
+}
+
+impl<T> NexsockCodec<T> {
+    pub fn new() -> Self {
+        Self {
+            max_payload_len: DEFAULT_MAX_PAYLOAD_LEN,
+            _body: PhantomData,
+        }
+    }
+
+    /// Rejects frames declaring a payload longer than `max` bytes with
+    /// [`ProtocolError::PayloadTooLarge`], before buffering them. Encoding a body longer than
+    /// that fails the same way, since no codec with this limit could decode it.
+    pub fn with_max_payload_len(mut self, max: u32) -> Self {
+        self.max_payload_len = max;
+        self
+    }
+}
+
+impl<T> Default for NexsockCodec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: MessageBody> Decoder for NexsockCodec<T> {
+    type Item = T;
+    type Error = ProtocolError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> ProtocolResult<Option<T>> {
+        if src.len() < MAGIC.len() {
+            return Ok(None);
+        }
+
+        if src[..MAGIC.len()] != MAGIC {
+            return Err(ProtocolError::InvalidMagic {
+                got: src[..MAGIC.len()].try_into().unwrap(),
+                expected: MAGIC,
+            });
+        }
+
+        if src.len() < PREFIX_SIZE {
+            src.reserve(PREFIX_SIZE - src.len());
+
+            return Ok(None);
+        }
+
+        let header = Header::parse::<StandardHeaderParser>(&src[MAGIC.len()..PREFIX_SIZE])
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Failed to parse header"))?;
+        let flags = header.flags();
+
+        if flags.intersects(MessageFlags::COMPRESSED | MessageFlags::ENCRYPTED) {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "NexsockCodec doesn't support compressed or encrypted frames",
+            )
+            .into());
+        }
+
+        let payload_len = if flags.contains(MessageFlags::HAS_PAYLOAD) {
+            header.payload_len()
+        } else {
+            0
+        };
+        if payload_len > self.max_payload_len {
+            return Err(ProtocolError::PayloadTooLarge {
+                len: payload_len.into(),
+                max: self.max_payload_len,
+            });
+        }
+
+        let trailer_len = if flags.contains(MessageFlags::HAS_CHECKSUM) {
+            CHECKSUM_SIZE
+        } else {
+            0
+        };
+        let frame_len = PREFIX_SIZE + payload_len as usize + trailer_len;
+
+        if src.len() < frame_len {
+            src.reserve(frame_len - src.len());
+
+            return Ok(None);
+        }
+
+        let frame = src.split_to(frame_len);
+        let payload_end = PREFIX_SIZE + payload_len as usize;
+        let payload = &frame[PREFIX_SIZE..payload_end];
+
+        if trailer_len > 0 {
+            let computed = checksum(&frame[MAGIC.len()..PREFIX_SIZE], payload)?;
+            let trailer = &frame[payload_end..];
+
+            if trailer != computed {
+                return Err(ProtocolError::ChecksumMismatch {
+                    expected: u32::from_be_bytes(computed),
+                    got: u32::from_be_bytes(trailer.try_into().unwrap()),
+                });
+            }
+        }
+
+        let config = bincode::config::standard().with_big_endian();
+        let (body, _) = bincode::decode_from_slice(payload, config)?;
+
+        Ok(Some(body))
+    }
+}
+
+impl<T: MessageBody> Encoder<Frame<{ HEADER_SIZE }, T>> for NexsockCodec<T> {
+    type Error = ProtocolError;
+
+    fn encode(
+        &mut self,
+        item: Frame<{ HEADER_SIZE }, T>,
+        dst: &mut BytesMut,
+    ) -> ProtocolResult<()> {
+        let header = Header::parse::<StandardHeaderParser>(&item.header()).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "Failed to parse frame header")
+        })?;
+
+        let config = bincode::config::standard().with_big_endian();
+        let payload = bincode::encode_to_vec(item.body(), config)?;
+
+        let len = payload_len(payload.len())?;
+        if len > self.max_payload_len {
+            return Err(ProtocolError::PayloadTooLarge {
+                len: len.into(),
+                max: self.max_payload_len,
+            });
+        }
+
+        let mut flags = header.flags() & !(MessageFlags::COMPRESSED | MessageFlags::ENCRYPTED);
+        if payload.is_empty() {
+            flags.remove(MessageFlags::HAS_PAYLOAD);
+        } else {
+            flags.insert(MessageFlags::HAS_PAYLOAD);
+        }
+
+        let header = Header::new(
+            header.id(),
+            header.version(),
+            flags,
+            len,
+            header.sequence_number(),
+        );
+
+        dst.reserve(PREFIX_SIZE + payload.len() + CHECKSUM_SIZE);
+        dst.put_slice(&MAGIC);
+        let header_start = dst.len();
+        StandardHeaderParser::serialize_to_buf(&header, dst);
+
+        let trailer = if flags.contains(MessageFlags::HAS_CHECKSUM) {
+            Some(checksum(&dst[header_start..], &payload)?)
+        } else {
+            None
+        };
+
+        dst.put_slice(&payload);
+        if let Some(trailer) = trailer {
+            dst.put_slice(&trailer);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::Transport;
+    use bincode::{Decode, Encode};
+    use futures::{SinkExt, StreamExt};
+    use tokio_util::codec::{FramedRead, FramedWrite};
+
+    #[derive(Debug, PartialEq, Encode, Decode)]
+    struct Ping {
+        id: u32,
+        text: String,
+    }
+
+    impl MessageBody for Ping {}
+
+    fn frame(seq: u64, text: &str) -> Frame<{ HEADER_SIZE }, Ping> {
+        Ping {
+            id: seq as u32,
+            text: text.to_string(),
+        }
+        .to_frame(Header::new(1, 1, MessageFlags::NONE, 0, seq).to_bytes::<StandardHeaderParser>())
+    }
+
+    #[tokio::test]
+    async fn test_framed_roundtrip() {
+        // A tiny pipe forces frames to arrive in pieces, splitting the magic as well.
+        let (client, server) = Transport::pair(3);
+        let (_, client) = client.into_inner();
+        let (server, _) = server.into_inner();
+
+        let mut sink = FramedWrite::new(client, NexsockCodec::<Ping>::new());
+        let mut stream = FramedRead::new(server, NexsockCodec::<Ping>::new());
+
+        let send = async {
+            sink.send(frame(1, "hello")).await.unwrap();
+            sink.send(frame(2, "world")).await.unwrap();
+            sink.close().await.unwrap();
+        };
+        let receive = async {
+            let mut received = Vec::new();
+            while let Some(message) = stream.next().await {
+                received.push(message.unwrap());
+            }
+
+            received
+        };
+
+        let ((), received) = tokio::join!(send, receive);
+
+        assert_eq!(
+            received,
+            [
+                Ping {
+                    id: 1,
+                    text: "hello".to_string()
+                },
+                Ping {
+                    id: 2,
+                    text: "world".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decode_split_magic() {
+        let mut codec = NexsockCodec::<Ping>::new();
+
+        let mut encoded = BytesMut::new();
+        codec.encode(frame(7, "split"), &mut encoded).unwrap();
+
+        let mut src = BytesMut::from(&encoded[..2]);
+        assert!(codec.decode(&mut src).unwrap().is_none());
+
+        src.extend_from_slice(&encoded[2..PREFIX_SIZE + 1]);
+        assert!(codec.decode(&mut src).unwrap().is_none());
+
+        src.extend_from_slice(&encoded[PREFIX_SIZE + 1..]);
+        assert_eq!(
+            codec.decode(&mut src).unwrap(),
+            Some(Ping {
+                id: 7,
+                text: "split".to_string()
+            })
+        );
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn test_encode_payload_too_large() {
+        let mut codec = NexsockCodec::<Ping>::new().with_max_payload_len(8);
+        let mut dst = BytesMut::new();
+
+        assert!(matches!(
+            codec.encode(frame(1, "longer than eight bytes"), &mut dst),
+            Err(ProtocolError::PayloadTooLarge { max: 8, .. })
+        ));
+        assert!(dst.is_empty());
+
+        codec.encode(frame(1, "short"), &mut dst).unwrap();
+        assert!(!dst.is_empty());
+    }
+
+    #[test]
+    fn test_decode_invalid_magic() {
+        let mut codec = NexsockCodec::<Ping>::new();
+        let mut src = BytesMut::from(&b"NOPE"[..]);
+
+        assert!(matches!(
+            codec.decode(&mut src),
+            Err(ProtocolError::InvalidMagic { got, expected: MAGIC }) if &got == b"NOPE"
+        ));
+    }
+}
@@ -1 +1,35 @@
-pub const HEADER_SIZE: usize = 15;
+//! Layout of the standard header: the packed id/version byte, then the flags, payload length and
+//! sequence number, all big-endian. Each offset follows from the size of the field before it, and
+//! every parser slices the header with these constants rather than literal indices.
+
+/// Offset of the flags, right after the id/version byte.
+pub const FLAGS_OFFSET: usize = 1;
+
+/// Offset of the payload length.
+pub const PAYLOAD_OFFSET: usize = FLAGS_OFFSET + size_of::<u16>();
+
+/// Offset of the sequence number.
+pub const SEQ_OFFSET: usize = PAYLOAD_OFFSET + size_of::<u32>();
+
+pub const HEADER_SIZE: usize = SEQ_OFFSET + size_of::<u64>();
+
+pub use crate::protocol_params::MAGIC;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offsets() {
+        let sizes = [1, size_of::<u16>(), size_of::<u32>(), size_of::<u64>()];
+        let offsets = [0, FLAGS_OFFSET, PAYLOAD_OFFSET, SEQ_OFFSET, HEADER_SIZE];
+
+        for (i, size) in sizes.into_iter().enumerate() {
+            assert_eq!(offsets[i] + size, offsets[i + 1], "field {i}");
+        }
+        assert_eq!(sizes.into_iter().sum::<usize>(), HEADER_SIZE);
+
+        // The wire format hasn't changed
+        assert_eq!(HEADER_SIZE, 15);
+    }
+}
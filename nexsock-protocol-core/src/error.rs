@@ -1,12 +1,81 @@
-use bincode::error::DecodeError;
+use bincode::error::{DecodeError, EncodeError};
 use thiserror::Error;
 
-pub type ProtocolResult<T, E = ProtocolError> = std::result::Result<T, E>;
+pub type ProtocolResult<T, E = ProtocolError> = core::result::Result<T, E>;
 
 #[derive(Error, Debug)]
 pub enum ProtocolError {
+    #[cfg(feature = "std")]
     #[error(transparent)]
     Io(#[from] std::io::Error),
+    #[error("invalid protocol magic bytes {got:02x?}, expected {expected:02x?}")]
+    InvalidMagic { got: [u8; 4], expected: [u8; 4] },
+    #[error("unsupported protocol version {got}, the highest supported is {supported}")]
+    UnsupportedVersion { got: u8, supported: u8 },
+    // bincode's errors only implement `Error` with `std`, so without it they're just displayed.
+    #[cfg_attr(feature = "std", error(transparent))]
+    #[cfg_attr(not(feature = "std"), error("{0}"))]
+    Decode(#[cfg_attr(feature = "std", from)] DecodeError),
+    #[cfg_attr(feature = "std", error(transparent))]
+    #[cfg_attr(not(feature = "std"), error("{0}"))]
+    Encode(#[cfg_attr(feature = "std", from)] EncodeError),
+    #[cfg(feature = "json")]
     #[error(transparent)]
-    Decode(#[from] DecodeError),
+    Json(#[from] serde_json::Error),
+    #[error("buffer too small: need {needed} bytes, got {got}")]
+    BufferTooSmall { needed: usize, got: usize },
+    #[error("payload of {len} bytes exceeds the maximum of {max}")]
+    PayloadTooLarge { len: u64, max: u32 },
+    #[error("{field} {value} is out of range, the maximum is {max}")]
+    FieldOutOfRange {
+        field: &'static str,
+        value: u64,
+        max: u64,
+    },
+    #[error("header has unknown flag bits set: {bits:#06x}")]
+    UnknownFlags { bits: u16 },
+    #[error("checksum mismatch: computed {expected:#010x}, frame carries {got:#010x}")]
+    ChecksumMismatch { expected: u32, got: u32 },
+    #[error("failed to decrypt the payload: wrong key, or the frame was tampered with")]
+    Decryption,
+    #[error("the payload is compressed or encrypted and has to be opened by the transport")]
+    TransformedPayload,
+    #[error("timed out waiting for the next frame")]
+    IdleTimeout,
+    #[error("timed out in the middle of a frame")]
+    FrameTimeout,
+    #[error("timed out waiting for the peer to send more data")]
+    Timeout,
+    #[error("message id {id} carries {expected}, but was read as {got}")]
+    TypeMismatch {
+        id: u8,
+        expected: &'static str,
+        got: &'static str,
+    },
+    #[error("no body type is registered for message id {id}")]
+    UnregisteredId { id: u8 },
+    #[error("message id {id} is registered as {expected} bytes, but its body took {got}")]
+    FixedLenMismatch { id: u8, expected: u32, got: usize },
+    #[error("sequence number went backwards: expected more than {expected_gt}, got {got}")]
+    SequenceRegression { expected_gt: u64, got: u64 },
+    #[error("expected the next fragment of message {sequence}, got frame {got}")]
+    FragmentMismatch { sequence: u64, got: u64 },
+    #[error("message {sequence} is fragmented, which this read doesn't support")]
+    UnexpectedFragment { sequence: u64 },
+    #[error("no acknowledgement for message {sequence} arrived in time")]
+    AckTimeout { sequence: u64 },
+}
+
+#[cfg(not(feature = "std"))]
+impl From<DecodeError> for ProtocolError {
+    fn from(err: DecodeError) -> Self {
+        Self::Decode(err)
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl From<EncodeError> for ProtocolError {
+    fn from(err: EncodeError) -> Self {
+        Self::Encode(err)
+    }
 }
@@ -1,13 +1,20 @@
+use crate::constants::{HEADER_SIZE, MAGIC};
+use crate::error::{ProtocolError, ProtocolResult};
+use crate::header::Header;
+use crate::header::standard::StandardHeaderParser;
+use crate::message_flags::MessageFlags;
 use crate::traits::MessageBody;
+use crate::traits::header::{HeaderDeserializer, HeaderSerializer};
 use bincode::{Decode, Encode};
+use bytes::Bytes;
 
-#[derive(Debug, /*Default, Clone, */ PartialEq, Eq, Ord, PartialOrd, Hash, Encode, Decode)]
-pub struct Frame<const N: usize, T: MessageBody> {
+#[derive(Debug, /*Default, */ Clone, PartialEq, Eq, Ord, PartialOrd, Hash, Encode, Decode)]
+pub struct Frame<const N: usize, T> {
     header: [u8; N],
     body: T,
 }
 
-impl<const N: usize, T: MessageBody> Frame<N, T> {
+impl<const N: usize, T> Frame<N, T> {
     pub fn new(header: [u8; N], body: T) -> Self {
         Self { header, body }
     }
@@ -19,4 +26,300 @@ impl<const N: usize, T: MessageBody> Frame<N, T> {
     pub fn body(&self) -> &T {
         &self.body
     }
+
+    /// Parses the header bytes with `P`. Returns `None` if they don't parse, or if the frame isn't
+    /// [`HEADER_SIZE`] bytes wide, since that's the only width the parsers read.
+    pub fn parsed_header<P: HeaderDeserializer>(&self) -> Option<Header> {
+        if N != HEADER_SIZE {
+            return None;
+        }
+
+        P::parse(&self.header)
+    }
+
+    /// Takes the body out of the frame, dropping the header.
+    pub fn into_body(self) -> T {
+        self.body
+    }
+
+    pub fn into_parts(self) -> ([u8; N], T) {
+        (self.header, self.body)
+    }
+}
+
+impl Frame<HEADER_SIZE, Bytes> {
+    /// Builds a frame around an already encoded payload, serializing `header` with `S`.
+    ///
+    /// The body is kept as opaque bytes, so the frame can be forwarded with
+    /// [`Transport::write_raw`](crate::transport::Transport::write_raw) without decoding it first.
+    /// `header` should describe `payload`, i.e. its `payload_len` must match the payload length.
+    pub fn from_encoded<S: HeaderSerializer>(header: Header, payload: Bytes) -> Self {
+        Self::new(header.to_bytes::<S>(), payload)
+    }
+}
+
+/// Splits an already encoded body into frames of at most `chunk_size` bytes of payload each,
+/// lazily, so callers can drive chunked writes with their own I/O loop, e.g. through
+/// [`Transport::write_raw`](crate::transport::Transport::write_raw).
+///
+/// The frames are laid out like [`TransportWriter::write_stream`] writes them: all flagged
+/// [`MessageFlags::FRAGMENTED`] and sharing `sequence_number`, with the last one also flagged
+/// [`MessageFlags::LAST_FRAGMENT`], so readers reassemble them into `data`. An empty `data` gives
+/// a single frame without payload. The chunks are slices of `data`, so nothing is copied.
+///
+/// # Panics
+///
+/// If `chunk_size` is 0 or doesn't fit in a `u32`.
+///
+/// [`TransportWriter::write_stream`]: crate::transport::TransportWriter::write_stream
+pub fn chunk_payload(
+    data: Bytes,
+    chunk_size: usize,
+    id: u8,
+    version: u8,
+    sequence_number: u64,
+) -> impl Iterator<Item = Frame<HEADER_SIZE, Bytes>> {
+    assert!(
+        chunk_size > 0 && chunk_size <= u32::MAX as usize,
+        "chunk size must be between 1 and u32::MAX bytes"
+    );
+
+    let count = data.len().div_ceil(chunk_size).max(1);
+
+    (0..count).map(move |index| {
+        let start = index * chunk_size;
+        let chunk = data.slice(start..data.len().min(start + chunk_size));
+
+        let mut flags = MessageFlags::FRAGMENTED;
+        if !chunk.is_empty() {
+            flags.insert(MessageFlags::HAS_PAYLOAD);
+        }
+        if index + 1 == count {
+            flags.insert(MessageFlags::LAST_FRAGMENT);
+        }
+
+        let header = Header::new(id, version, flags, chunk.len() as u32, sequence_number);
+
+        Frame::from_encoded::<StandardHeaderParser>(header, chunk)
+    })
+}
+
+/// A frame whose body is the raw payload, borrowed from the buffer it was read into rather than
+/// decoded, see [`Transport::read_frame_raw`](crate::transport::Transport::read_frame_raw).
+///
+/// The body is exactly what was on the wire, so a proxy can forward the frame with
+/// [`Transport::write_frame_ref`](crate::transport::Transport::write_frame_ref) without touching
+/// it, and only [decode](FrameRef::decode) the frames it needs to look into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameRef<'a, const N: usize> {
+    header: [u8; N],
+    parsed: Header,
+    body: &'a [u8],
+}
+
+impl<'a, const N: usize> FrameRef<'a, N> {
+    /// `header` should be `parsed` serialized, and `parsed` should describe `body`.
+    pub fn new(header: [u8; N], parsed: Header, body: &'a [u8]) -> Self {
+        Self {
+            header,
+            parsed,
+            body,
+        }
+    }
+
+    pub fn header(&self) -> [u8; N] {
+        self.header
+    }
+
+    pub fn parsed_header(&self) -> Header {
+        self.parsed
+    }
+
+    pub fn body(&self) -> &'a [u8] {
+        self.body
+    }
+
+    /// Decodes the body as `T`.
+    ///
+    /// Compressed or encrypted bodies can only be opened by the transport, so they fail with
+    /// [`ProtocolError::TransformedPayload`]; read those with
+    /// [`Transport::read_message`](crate::transport::Transport::read_message) instead.
+    pub fn decode<T: MessageBody>(&self) -> ProtocolResult<T> {
+        if self
+            .parsed
+            .flags()
+            .intersects(MessageFlags::COMPRESSED | MessageFlags::ENCRYPTED)
+        {
+            return Err(ProtocolError::TransformedPayload);
+        }
+
+        let config = bincode::config::standard().with_big_endian();
+        let (body, _) = bincode::decode_from_slice(self.body, config)?;
+
+        Ok(body)
+    }
+}
+
+/// Checks that `bytes` starts with [`MAGIC`], e.g. when picking frames out of a capture without a
+/// transport. Anything after the magic is ignored.
+pub fn verify_magic(bytes: &[u8]) -> ProtocolResult<()> {
+    let got: [u8; 4] = bytes
+        .get(..MAGIC.len())
+        .and_then(|magic| magic.try_into().ok())
+        .ok_or(ProtocolError::BufferTooSmall {
+            needed: MAGIC.len(),
+            got: bytes.len(),
+        })?;
+
+    if got != MAGIC {
+        return Err(ProtocolError::InvalidMagic {
+            got,
+            expected: MAGIC,
+        });
+    }
+
+    Ok(())
+}
+
+/// Encodes `msg` into a complete frame on the wire (magic, header and payload), parses it back
+/// and asserts that both the header fields and the decoded body match what went in.
+///
+/// Meant for testing message types; available with the `testing` feature.
+#[cfg(any(test, feature = "testing"))]
+pub fn assert_roundtrip<T: MessageBody + PartialEq + core::fmt::Debug>(
+    msg: T,
+    id: u8,
+    version: u8,
+    flags: crate::message_flags::MessageFlags,
+    sequence_number: u64,
+) {
+    use alloc::vec::Vec;
+
+    let config = bincode::config::standard().with_big_endian();
+    let payload = bincode::encode_to_vec(&msg, config).expect("failed to encode message body");
+
+    let flags = if payload.is_empty() {
+        flags
+    } else {
+        flags | MessageFlags::HAS_PAYLOAD
+    };
+    let header = Header::new(id, version, flags, payload.len() as u32, sequence_number);
+
+    let mut wire = Vec::with_capacity(4 + HEADER_SIZE + payload.len());
+    wire.extend_from_slice(&MAGIC);
+    wire.extend_from_slice(&header.to_bytes::<StandardHeaderParser>());
+    wire.extend_from_slice(&payload);
+
+    let parsed = Header::parse::<StandardHeaderParser>(&wire[4..]).expect("failed to parse header");
+    assert_eq!(parsed.id(), id, "id mismatch");
+    assert_eq!(parsed.version(), version, "version mismatch");
+    assert_eq!(parsed.flags(), flags, "flags mismatch");
+    assert_eq!(
+        parsed.payload_len() as usize,
+        payload.len(),
+        "payload_len mismatch"
+    );
+    assert_eq!(
+        parsed.sequence_number(),
+        sequence_number,
+        "sequence_number mismatch"
+    );
+
+    let (decoded, _): (T, _) = bincode::decode_from_slice(&wire[4 + HEADER_SIZE..], config)
+        .expect("failed to decode message body");
+    assert_eq!(decoded, msg, "body mismatch");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::{String, ToString};
+    use alloc::vec::Vec;
+
+    fn header_bytes() -> [u8; HEADER_SIZE] {
+        Header::new(2, 1, MessageFlags::NONE, 0, 5).to_bytes::<StandardHeaderParser>()
+    }
+
+    #[test]
+    fn test_clone() {
+        let frame = Frame::new(header_bytes(), "body".to_string());
+        let cloned = frame.clone();
+
+        assert_eq!(cloned, frame);
+        assert_eq!(cloned.into_body(), "body");
+    }
+
+    #[test]
+    fn test_parsed_header() {
+        let header = Header::new(
+            7,
+            2,
+            MessageFlags::REQUIRES_ACK | MessageFlags::HAS_PAYLOAD,
+            3,
+            11,
+        );
+        let frame = Frame::new(header.to_bytes::<StandardHeaderParser>(), ());
+
+        assert_eq!(frame.parsed_header::<StandardHeaderParser>(), Some(header));
+        assert_eq!(
+            Frame::new([0u8; 4], ()).parsed_header::<StandardHeaderParser>(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_into_parts() {
+        let frame = Frame::new(header_bytes(), String::from("body"));
+        let (header, body) = frame.into_parts();
+
+        assert_eq!(header, header_bytes());
+        assert_eq!(body, "body");
+    }
+
+    #[test]
+    fn test_chunk_payload() {
+        let data: Bytes = (0..10 * 1024).map(|i| i as u8).collect();
+        let frames: Vec<_> = chunk_payload(data.clone(), 4 * 1024, 3, 1, 9).collect();
+        assert_eq!(frames.len(), 3);
+
+        let mut reassembled = Vec::new();
+        for (index, frame) in frames.iter().enumerate() {
+            let header = frame.parsed_header::<StandardHeaderParser>().unwrap();
+            assert_eq!((header.id(), header.version()), (3, 1));
+            assert_eq!(header.sequence_number(), 9);
+            assert_eq!(header.payload_len() as usize, frame.body().len());
+            assert!(header.flags().contains(MessageFlags::FRAGMENTED));
+            assert_eq!(
+                header.flags().contains(MessageFlags::LAST_FRAGMENT),
+                index == 2
+            );
+
+            reassembled.extend_from_slice(frame.body());
+        }
+        assert_eq!(frames[2].body().len(), 2 * 1024);
+        assert_eq!(reassembled, data);
+
+        let empty: Vec<_> = chunk_payload(Bytes::new(), 4 * 1024, 3, 1, 9).collect();
+        assert_eq!(empty.len(), 1);
+        let header = empty[0].parsed_header::<StandardHeaderParser>().unwrap();
+        assert_eq!(
+            header.flags(),
+            MessageFlags::FRAGMENTED | MessageFlags::LAST_FRAGMENT
+        );
+    }
+
+    #[test]
+    fn test_verify_magic() {
+        assert!(verify_magic(b"NEX\0").is_ok());
+        assert!(verify_magic(b"NEX\0\x05rest of the frame").is_ok());
+
+        assert!(matches!(
+            verify_magic(b"NEX\x01"),
+            Err(ProtocolError::InvalidMagic { got, expected: MAGIC }) if got == *b"NEX\x01"
+        ));
+        assert!(matches!(
+            verify_magic(b"NE"),
+            Err(ProtocolError::BufferTooSmall { needed: 4, got: 2 })
+        ));
+    }
 }
@@ -0,0 +1,101 @@
+use crate::error::ProtocolResult;
+use crate::header::Header;
+use crate::message_flags::MessageFlags;
+
+/// Builds a [`Header`] field by field, see [`Header::builder`].
+///
+/// Fields that aren't set default to zero, and flags to [`MessageFlags::NONE`].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct HeaderBuilder {
+    id: u8,
+    version: u8,
+    flags: MessageFlags,
+    payload_len: u32,
+    sequence_number: u64,
+}
+
+impl HeaderBuilder {
+    pub fn id(mut self, id: u8) -> Self {
+        self.id = id;
+        self
+    }
+
+    pub fn version(mut self, version: u8) -> Self {
+        self.version = version;
+        self
+    }
+
+    pub fn flags(mut self, flags: MessageFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    pub fn payload_len(mut self, payload_len: u32) -> Self {
+        self.payload_len = payload_len;
+        self
+    }
+
+    pub fn sequence_number(mut self, sequence_number: u64) -> Self {
+        self.sequence_number = sequence_number;
+        self
+    }
+
+    /// Builds the header, checking that the id and version fit on the wire, see
+    /// [`Header::try_new`].
+    pub fn build(self) -> ProtocolResult<Header> {
+        Header::try_new(
+            self.id,
+            self.version,
+            self.flags,
+            self.payload_len,
+            self.sequence_number,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ProtocolError;
+
+    #[test]
+    fn test_build() {
+        let header = Header::builder()
+            .id(63)
+            .version(3)
+            .flags(MessageFlags::REQUIRES_ACK)
+            .payload_len(0x200)
+            .sequence_number(42)
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            header,
+            Header::new(63, 3, MessageFlags::REQUIRES_ACK, 0x200, 42)
+        );
+        assert_eq!(
+            Header::builder().build().unwrap(),
+            Header::new(0, 0, MessageFlags::NONE, 0, 0)
+        );
+    }
+
+    #[test]
+    fn test_build_out_of_range() {
+        assert!(matches!(
+            Header::builder().id(64).build(),
+            Err(ProtocolError::FieldOutOfRange {
+                field: "id",
+                value: 64,
+                max: 63
+            })
+        ));
+        assert!(matches!(
+            Header::builder().version(4).build(),
+            Err(ProtocolError::FieldOutOfRange {
+                field: "version",
+                value: 4,
+                max: 3
+            })
+        ));
+    }
+}
@@ -0,0 +1,144 @@
+//! Four byte header layout for small control protocols, where ids, payloads and sequence numbers
+//! all stay tiny.
+//!
+//! | bytes  | bits    | field             |
+//! |--------|---------|-------------------|
+//! | `0..2` | `15-12` | id                |
+//! | `0..2` | `11-10` | version           |
+//! | `0..2` | `9-0`   | flags             |
+//! | `2`    | `7-0`   | payload length    |
+//! | `3`    | `7-0`   | sequence number   |
+//!
+//! The first two bytes form a big endian `u16`. Headers with fields that don't fit are rejected
+//! on serialization rather than truncated. Both peers have to agree on this layout up front, see
+//! `Transport::with_compact_header`.
+
+use crate::error::{ProtocolError, ProtocolResult};
+use crate::header::Header;
+use crate::message_flags::MessageFlags;
+
+/// Size of a compact header.
+pub const COMPACT_HEADER_SIZE: usize = 4;
+/// Largest id a compact header can carry.
+pub const COMPACT_MAX_ID: u8 = 0x0F;
+/// Mask of the flag bits a compact header can carry.
+pub const COMPACT_FLAGS_MASK: u16 = 0x03FF;
+/// Largest payload length a compact header can carry.
+pub const COMPACT_MAX_PAYLOAD_LEN: u32 = u8::MAX as u32;
+/// Largest sequence number a compact header can carry.
+pub const COMPACT_MAX_SEQUENCE_NUMBER: u64 = u8::MAX as u64;
+
+fn check_range(field: &'static str, value: u64, max: u64) -> ProtocolResult<()> {
+    if value > max {
+        return Err(ProtocolError::FieldOutOfRange { field, value, max });
+    }
+
+    Ok(())
+}
+
+pub struct CompactHeaderParser;
+
+impl CompactHeaderParser {
+    /// Serializes `header`, failing with [`ProtocolError::FieldOutOfRange`] if any of its fields
+    /// doesn't fit the compact layout.
+    pub fn serialize(header: &Header) -> ProtocolResult<[u8; COMPACT_HEADER_SIZE]> {
+        check_range("id", header.id() as u64, COMPACT_MAX_ID as u64)?;
+        check_range("flags", *header.flags() as u64, COMPACT_FLAGS_MASK as u64)?;
+        check_range(
+            "payload_len",
+            header.payload_len() as u64,
+            COMPACT_MAX_PAYLOAD_LEN as u64,
+        )?;
+        check_range(
+            "sequence_number",
+            header.sequence_number(),
+            COMPACT_MAX_SEQUENCE_NUMBER,
+        )?;
+
+        let word = ((header.id() as u16) << 12)
+            | (((header.version() & Header::VERSION_MASK) as u16) << 10)
+            | *header.flags();
+
+        let mut buf = [0u8; COMPACT_HEADER_SIZE];
+        buf[0..2].copy_from_slice(&word.to_be_bytes());
+        buf[2] = header.payload_len() as u8;
+        buf[3] = header.sequence_number() as u8;
+
+        Ok(buf)
+    }
+
+    /// Parses a compact header from the start of `bytes`. Returns `None` if `bytes` is too short.
+    pub fn parse(bytes: &[u8]) -> Option<Header> {
+        if bytes.len() < COMPACT_HEADER_SIZE {
+            return None;
+        }
+
+        let word = u16::from_be_bytes([bytes[0], bytes[1]]);
+
+        Some(Header::new(
+            (word >> 12) as u8,
+            ((word >> 10) as u8) & Header::VERSION_MASK,
+            MessageFlags::from(word & COMPACT_FLAGS_MASK),
+            bytes[2] as u32,
+            bytes[3] as u64,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compact_roundtrip() {
+        for id in 0..=COMPACT_MAX_ID {
+            for version in 0..=Header::VERSION_MASK {
+                for bits in 0..=MessageFlags::KNOWN_MASK {
+                    for (payload_len, sequence_number) in [(0, 0), (1, 255), (255, 1), (255, 255)] {
+                        let header = Header::new(
+                            id,
+                            version,
+                            MessageFlags::from(bits),
+                            payload_len,
+                            sequence_number,
+                        );
+
+                        let bytes = CompactHeaderParser::serialize(&header).unwrap();
+                        assert_eq!(CompactHeaderParser::parse(&bytes), Some(header));
+                        assert_eq!(CompactHeaderParser::parse(&bytes[..3]), None);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_compact_rejects_out_of_range() {
+        let cases = [
+            (Header::new(16, 0, MessageFlags::NONE, 0, 0), "id", 16),
+            (
+                Header::new(1, 0, MessageFlags::from(0x0400), 0, 0),
+                "flags",
+                0x0400,
+            ),
+            (
+                Header::new(1, 0, MessageFlags::NONE, 256, 0),
+                "payload_len",
+                256,
+            ),
+            (
+                Header::new(1, 0, MessageFlags::NONE, 0, 256),
+                "sequence_number",
+                256,
+            ),
+        ];
+
+        for (header, expected_field, expected_value) in cases {
+            assert!(matches!(
+                CompactHeaderParser::serialize(&header),
+                Err(ProtocolError::FieldOutOfRange { field, value, .. })
+                    if field == expected_field && value == expected_value
+            ));
+        }
+    }
+}
@@ -0,0 +1,127 @@
+//! The standard header layout with its multi-byte fields in little-endian order, for peers (mostly
+//! embedded ones) that emit native little-endian headers.
+//!
+//! Only the header changes: the id/version byte is the same as in the standard layout, and the
+//! magic in front of a frame stays fixed.
+
+use crate::constants::{FLAGS_OFFSET, HEADER_SIZE, PAYLOAD_OFFSET, SEQ_OFFSET};
+use crate::error::{ProtocolError, ProtocolResult};
+use crate::header::Header;
+use crate::message_flags::MessageFlags;
+use crate::protocol_params::VERSION_BITS;
+use crate::traits::header::{HeaderDeserializer, HeaderSerializer};
+use bytes::{BufMut, BytesMut};
+
+pub struct LittleEndianHeaderParser;
+
+impl HeaderDeserializer for LittleEndianHeaderParser {
+    #[inline]
+    fn parse(bytes: &[u8]) -> Option<Header> {
+        if bytes.len() < HEADER_SIZE {
+            return None;
+        }
+
+        let id = bytes[0] >> VERSION_BITS;
+        let version = bytes[0] & Header::VERSION_MASK;
+
+        let flags = u16::from_le_bytes(bytes[FLAGS_OFFSET..PAYLOAD_OFFSET].try_into().unwrap());
+        let payload_len = u32::from_le_bytes(bytes[PAYLOAD_OFFSET..SEQ_OFFSET].try_into().unwrap());
+        let sequence_number =
+            u64::from_le_bytes(bytes[SEQ_OFFSET..HEADER_SIZE].try_into().unwrap());
+
+        Some(Header::new(
+            id,
+            version,
+            MessageFlags::from(flags),
+            payload_len,
+            sequence_number,
+        ))
+    }
+}
+
+impl HeaderSerializer for LittleEndianHeaderParser {
+    #[inline]
+    fn serialize(header: &Header) -> [u8; HEADER_SIZE] {
+        let mut bytes = [0u8; HEADER_SIZE];
+        Self::serialize_into(header, &mut bytes).expect("buffer is exactly one header long");
+
+        bytes
+    }
+
+    #[inline]
+    fn serialize_into(header: &Header, dst: &mut [u8]) -> ProtocolResult<()> {
+        if dst.len() < HEADER_SIZE {
+            return Err(ProtocolError::BufferTooSmall {
+                needed: HEADER_SIZE,
+                got: dst.len(),
+            });
+        }
+
+        dst[0] = ((header.id & Header::ID_MASK) << VERSION_BITS)
+            | (header.version & Header::VERSION_MASK);
+        dst[FLAGS_OFFSET..PAYLOAD_OFFSET].copy_from_slice(&(*header.flags).to_le_bytes());
+        dst[PAYLOAD_OFFSET..SEQ_OFFSET].copy_from_slice(&header.payload_len.to_le_bytes());
+        dst[SEQ_OFFSET..HEADER_SIZE].copy_from_slice(&header.sequence_number.to_le_bytes());
+
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_to_buf(header: &Header, buf: &mut BytesMut) {
+        buf.reserve(HEADER_SIZE);
+        buf.put_u8(
+            ((header.id & Header::ID_MASK) << VERSION_BITS)
+                | (header.version & Header::VERSION_MASK),
+        );
+        buf.put_u16_le(*header.flags);
+        buf.put_u32_le(header.payload_len);
+        buf.put_u64_le(header.sequence_number);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::assert_inverse;
+    use crate::header::standard::StandardHeaderParser;
+    use crate::header::tests::test_id_version_roundtrip;
+
+    #[test]
+    fn test_little_endian_byte_order() {
+        let header = Header::new(
+            1,
+            2,
+            MessageFlags::HAS_PAYLOAD | MessageFlags::COMPRESSED,
+            0x0102_0304,
+            0x0102_0304_0506_0708,
+        );
+
+        let big = header.to_bytes::<StandardHeaderParser>();
+        let little = header.to_bytes::<LittleEndianHeaderParser>();
+
+        assert_eq!(little[0], big[0]);
+        assert_eq!(little[1..3], [0x09, 0x00]);
+        assert_eq!(little[3..7], [0x04, 0x03, 0x02, 0x01]);
+        assert_eq!(
+            little[7..15],
+            [0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01]
+        );
+
+        for (big, little) in [(&big[1..3], &little[1..3]), (&big[3..7], &little[3..7])] {
+            assert!(big.iter().eq(little.iter().rev()));
+        }
+        assert!(big[7..15].iter().eq(little[7..15].iter().rev()));
+
+        assert_eq!(
+            Header::parse::<LittleEndianHeaderParser>(&little),
+            Some(header)
+        );
+        assert_ne!(Header::parse::<StandardHeaderParser>(&little), Some(header));
+    }
+
+    #[test]
+    fn test_little_endian_roundtrip() {
+        assert_inverse::<LittleEndianHeaderParser, LittleEndianHeaderParser>();
+        test_id_version_roundtrip::<LittleEndianHeaderParser, LittleEndianHeaderParser>();
+    }
+}
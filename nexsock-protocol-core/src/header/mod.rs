@@ -1,44 +1,166 @@
-use crate::constants::HEADER_SIZE;
-use crate::error::ProtocolResult;
-#[cfg(target_arch = "aarch64")]
-use crate::header::simd::Aarch64NeonHeaderParser;
-#[cfg(not(feature = "simd"))]
-use crate::header::standard::StandardHeaderParser;
-#[cfg(not(target_arch = "aarch64"))]
-use crate::header::standard::StandardHeaderParser;
+use crate::constants::{FLAGS_OFFSET, HEADER_SIZE, PAYLOAD_OFFSET, SEQ_OFFSET};
+use crate::error::{ProtocolError, ProtocolResult};
 use crate::message_flags::MessageFlags;
+use crate::protocol_params::{ID_BITS, VERSION_BITS};
 use crate::traits::header::{HeaderDeserializer, HeaderParser, HeaderSerializer};
+use builder::HeaderBuilder;
 use bytes::Bytes;
-use futures::AsyncRead;
-#[cfg(feature = "simd")]
-use optimized::OptimizedHeaderParser;
+#[cfg(feature = "std")]
+use tokio::io::AsyncRead;
 
+pub mod builder;
+pub mod compact;
+pub mod little_endian;
 pub mod optimized;
+#[cfg(feature = "std")]
+pub mod runtime;
 pub mod simd;
 pub mod standard;
+pub mod varint;
 
 /// Default parser combination based on configuration
 pub struct DefaultHeaderParser;
 
 impl HeaderParser for DefaultHeaderParser {
     cfg_if::cfg_if! {
-        if #[cfg(all(target_arch = "aarch64", target_feature = "neon"))] {
+        if #[cfg(all(feature = "simd", target_arch = "aarch64", target_feature = "neon"))] {
             type Serializer = crate::header::simd::Aarch64NeonHeaderParser;
+        } else if #[cfg(all(feature = "simd", target_arch = "x86_64", target_feature = "sse2"))] {
+            type Serializer = crate::header::simd::X86SimdHeaderParser;
         } else {
             type Serializer = crate::header::standard::StandardHeaderParser;
         }
     }
 
     cfg_if::cfg_if! {
-        if #[cfg(all(target_arch = "aarch64", target_feature = "neon"))] {
+        if #[cfg(all(feature = "simd", target_arch = "aarch64", target_feature = "neon"))] {
             type Deserializer = crate::header::simd::Aarch64NeonHeaderParser;
+        } else if #[cfg(all(feature = "simd", target_arch = "x86_64", target_feature = "sse2"))] {
+            type Deserializer = crate::header::simd::X86SimdHeaderParser;
         } else {
             type Deserializer = crate::header::optimized::OptimizedHeaderParser;
         }
     }
 }
 
+/// Reads only the message `id` from a serialized header, without parsing the rest of it.
+#[inline]
+pub fn id_only(buf: &[u8]) -> Option<u8> {
+    if buf.len() < HEADER_SIZE {
+        return None;
+    }
+
+    Some(buf[0] >> VERSION_BITS)
+}
+
+/// Reads only the `payload_len` from a serialized header, without parsing the rest of it.
+#[inline]
+pub fn payload_len_only(buf: &[u8]) -> Option<u32> {
+    if buf.len() < HEADER_SIZE {
+        return None;
+    }
+
+    Some(u32::from_be_bytes(
+        buf[PAYLOAD_OFFSET..SEQ_OFFSET].try_into().ok()?,
+    ))
+}
+
+/// The fields of a standard header that could be recovered from a possibly truncated buffer, see
+/// [`parse_lenient`]. Fields whose bytes weren't all there are `None`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PartialHeader {
+    pub id: Option<u8>,
+    pub version: Option<u8>,
+    pub flags: Option<MessageFlags>,
+    pub payload_len: Option<u32>,
+    pub sequence_number: Option<u64>,
+}
+
+impl PartialHeader {
+    /// Whether every field was recovered.
+    pub fn is_complete(&self) -> bool {
+        self.to_header().is_some()
+    }
+
+    /// The full header, if every field was recovered.
+    pub fn to_header(&self) -> Option<Header> {
+        Some(Header::new(
+            self.id?,
+            self.version?,
+            self.flags?,
+            self.payload_len?,
+            self.sequence_number?,
+        ))
+    }
+}
+
+/// Recovers whatever fields of a standard header `buf` holds, for diagnosing truncated captures.
+///
+/// Unlike [`Header::parse`], a short buffer isn't an error: the id and version come from the first
+/// byte, the flags need [`PAYLOAD_OFFSET`] bytes, the payload length [`SEQ_OFFSET`] and the
+/// sequence number all [`HEADER_SIZE`].
+pub fn parse_lenient(buf: &[u8]) -> PartialHeader {
+    let id_version = buf.first().copied();
+
+    PartialHeader {
+        id: id_version.map(|byte| byte >> VERSION_BITS),
+        version: id_version.map(|byte| byte & Header::VERSION_MASK),
+        flags: buf
+            .get(FLAGS_OFFSET..PAYLOAD_OFFSET)
+            .map(|bytes| MessageFlags::from(u16::from_be_bytes(bytes.try_into().unwrap()))),
+        payload_len: buf
+            .get(PAYLOAD_OFFSET..SEQ_OFFSET)
+            .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap())),
+        sequence_number: buf
+            .get(SEQ_OFFSET..HEADER_SIZE)
+            .map(|bytes| u64::from_be_bytes(bytes.try_into().unwrap())),
+    }
+}
+
+/// Asserts that `D` parses back exactly what `S` serializes, sweeping every id, version and
+/// combination of defined flags against boundary payload lengths and sequence numbers.
+///
+/// Meant for testing custom parser implementations; available with the `testing` feature.
+#[cfg(any(test, feature = "testing"))]
+pub fn assert_inverse<S: HeaderSerializer, D: HeaderDeserializer>() {
+    const PAYLOAD_LENS: [u32; 5] = [0, 1, 0xFF, 0x100, u32::MAX];
+    const SEQUENCE_NUMBERS: [u64; 4] = [0, 1, 0x0102_0304_0506_0708, u64::MAX];
+
+    for id in 0..=Header::ID_MASK {
+        for version in 0..=Header::VERSION_MASK {
+            for bits in 0..=MessageFlags::KNOWN_MASK {
+                if bits & !MessageFlags::KNOWN_MASK != 0 {
+                    continue;
+                }
+
+                for payload_len in PAYLOAD_LENS {
+                    for sequence_number in SEQUENCE_NUMBERS {
+                        let header = Header::new(
+                            id,
+                            version,
+                            MessageFlags::from(bits),
+                            payload_len,
+                            sequence_number,
+                        );
+
+                        assert_eq!(
+                            D::parse(&S::serialize(&header)),
+                            Some(header),
+                            "header did not survive a serialize/parse round trip"
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// With the `serde` feature, a header (de)serializes as a struct with named fields. This is meant
+/// for logs and config, the wire format is unaffected. Deserializing validates the id and version
+/// like [`Header::try_new`].
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "HeaderFields"))]
 pub struct Header {
     id: u8,
     version: u8,
@@ -47,10 +169,54 @@ pub struct Header {
     sequence_number: u64,
 }
 
-impl Header {
-    pub(crate) const LAST_SIX_BITS: u8 = 0x3F;
-    pub(crate) const LAST_TWO_BITS: u8 = 0x03;
+/// Unvalidated mirror of [`Header`] that serde deserializes into first.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct HeaderFields {
+    id: u8,
+    version: u8,
+    flags: MessageFlags,
+    payload_len: u32,
+    sequence_number: u64,
+}
 
+#[cfg(feature = "serde")]
+impl TryFrom<HeaderFields> for Header {
+    type Error = ProtocolError;
+
+    fn try_from(fields: HeaderFields) -> ProtocolResult<Self> {
+        Header::try_new(
+            fields.id,
+            fields.version,
+            fields.flags,
+            fields.payload_len,
+            fields.sequence_number,
+        )
+    }
+}
+
+#[inline]
+fn check_range(field: &'static str, value: u8, max: u8) -> ProtocolResult<()> {
+    if value > max {
+        return Err(ProtocolError::FieldOutOfRange {
+            field,
+            value: value as u64,
+            max: max as u64,
+        });
+    }
+
+    Ok(())
+}
+
+impl Header {
+    pub(crate) const ID_MASK: u8 = ((1u16 << ID_BITS) - 1) as u8;
+    pub(crate) const VERSION_MASK: u8 = ((1u16 << VERSION_BITS) - 1) as u8;
+
+    /// Creates a header without checking the id and version.
+    ///
+    /// The id only has [`ID_BITS`] bits and the version [`VERSION_BITS`] on the wire, so anything
+    /// larger gets truncated on serialization. This is a debug assertion; use [`Header::try_new`]
+    /// for values that aren't known to be in range.
     #[inline(always)]
     pub fn new(
         id: u8,
@@ -59,6 +225,12 @@ impl Header {
         payload_len: u32,
         sequence_number: u64,
     ) -> Self {
+        debug_assert!(id <= Self::ID_MASK, "header id {id} exceeds {ID_BITS} bits");
+        debug_assert!(
+            version <= Self::VERSION_MASK,
+            "header version {version} exceeds {VERSION_BITS} bits"
+        );
+
         Self {
             id,
             version,
@@ -68,21 +240,66 @@ impl Header {
         }
     }
 
+    /// Like [`Header::new`], but returns [`ProtocolError::FieldOutOfRange`] when the id doesn't fit
+    /// in [`ID_BITS`] bits or the version in [`VERSION_BITS`].
+    pub fn try_new(
+        id: u8,
+        version: u8,
+        flags: MessageFlags,
+        payload_len: u32,
+        sequence_number: u64,
+    ) -> ProtocolResult<Self> {
+        check_range("id", id, Self::ID_MASK)?;
+        check_range("version", version, Self::VERSION_MASK)?;
+
+        Ok(Self::new(id, version, flags, payload_len, sequence_number))
+    }
+
+    /// Starts building a header field by field, validating the id and version on
+    /// [`HeaderBuilder::build`].
+    #[inline]
+    pub fn builder() -> HeaderBuilder {
+        HeaderBuilder::default()
+    }
+
     #[inline(always)]
     pub fn to_bytes<S: HeaderSerializer>(&self) -> [u8; HEADER_SIZE] {
         S::serialize(self)
     }
 
+    #[inline(always)]
+    pub fn serialize_into<S: HeaderSerializer>(&self, dst: &mut [u8]) -> ProtocolResult<()> {
+        S::serialize_into(self, dst)
+    }
+
+    /// Parses a header from the start of `bytes` without consuming anything. This is what the
+    /// transport uses, since it reads each header into a buffer of its own.
     #[inline(always)]
     pub fn parse<P: HeaderDeserializer>(bytes: &[u8]) -> Option<Self> {
         P::parse(bytes)
     }
 
+    /// Parses a header from the start of `bytes` and advances past it, for consumers working
+    /// through a buffer holding several frames.
     #[inline(always)]
     pub fn parse_bytes<P: HeaderDeserializer>(bytes: &mut Bytes) -> Option<Self> {
         P::parse_bytes(bytes)
     }
 
+    /// Serializes the header with [`DefaultHeaderParser`], for callers that don't care which
+    /// serializer is used.
+    #[inline(always)]
+    pub fn encode(&self) -> [u8; HEADER_SIZE] {
+        self.to_bytes::<<DefaultHeaderParser as HeaderParser>::Serializer>()
+    }
+
+    /// Parses a header with [`DefaultHeaderParser`], the counterpart of [`Header::encode`].
+    #[inline(always)]
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        Self::parse::<<DefaultHeaderParser as HeaderParser>::Deserializer>(bytes)
+    }
+
+    #[cfg(feature = "std")]
     #[inline(always)]
     pub async fn read_header<P: HeaderDeserializer, R: AsyncRead + Unpin>(
         reader: &mut R,
@@ -114,12 +331,110 @@ impl Header {
     pub fn sequence_number(&self) -> u64 {
         self.sequence_number
     }
+
+    /// Returns a copy of the header with the id replaced. Like [`Header::new`], the id isn't
+    /// checked beyond a debug assertion.
+    #[inline(always)]
+    pub fn with_id(self, id: u8) -> Self {
+        Self::new(
+            id,
+            self.version,
+            self.flags,
+            self.payload_len,
+            self.sequence_number,
+        )
+    }
+
+    /// Returns a copy of the header with the version replaced, checked like the id in
+    /// [`Header::with_id`].
+    #[inline(always)]
+    pub fn with_version(self, version: u8) -> Self {
+        Self::new(
+            self.id,
+            version,
+            self.flags,
+            self.payload_len,
+            self.sequence_number,
+        )
+    }
+
+    /// Returns a copy of the header with the flags replaced, e.g.
+    /// `header.with_flags(header.flags() & !MessageFlags::REQUIRES_ACK)`.
+    #[inline(always)]
+    pub fn with_flags(self, flags: MessageFlags) -> Self {
+        Self { flags, ..self }
+    }
+
+    /// Sets `flag` in place, leaving the other flags and fields alone.
+    #[inline(always)]
+    pub fn set_flag(&mut self, flag: MessageFlags) {
+        self.flags.insert(flag);
+    }
+
+    /// Clears `flag` in place, leaving the other flags and fields alone.
+    #[inline(always)]
+    pub fn clear_flag(&mut self, flag: MessageFlags) {
+        self.flags.remove(flag);
+    }
+
+    #[inline(always)]
+    pub fn with_payload_len(self, payload_len: u32) -> Self {
+        Self {
+            payload_len,
+            ..self
+        }
+    }
+
+    #[inline(always)]
+    pub fn with_sequence_number(self, sequence_number: u64) -> Self {
+        Self {
+            sequence_number,
+            ..self
+        }
+    }
+}
+
+/// Orders headers by sequence number alone, e.g. to keep out-of-order frames in a
+/// [`BinaryHeap`](alloc::collections::BinaryHeap) until the gap before them is filled.
+///
+/// `Header` itself compares every field for equality, so it has no ordering of its own. Two
+/// `SeqOrdered` headers with the same sequence number compare (and hash) as equal even if their
+/// other fields differ. Wrap in [`Reverse`](core::cmp::Reverse) for the lowest number first.
+#[derive(Debug, Clone, Copy)]
+pub struct SeqOrdered(pub Header);
+
+impl PartialEq for SeqOrdered {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.sequence_number == other.0.sequence_number
+    }
+}
+
+impl Eq for SeqOrdered {}
+
+impl PartialOrd for SeqOrdered {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SeqOrdered {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        self.0.sequence_number.cmp(&other.0.sequence_number)
+    }
+}
+
+impl core::hash::Hash for SeqOrdered {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.0.sequence_number.hash(state);
+    }
 }
 
 #[cfg(test)]
 pub(crate) mod tests {
     use super::*;
     use crate::header::standard::StandardHeaderParser;
+    use alloc::vec::Vec;
+    use proptest::prelude::*;
 
     const HEADER_BYTES: [u8; HEADER_SIZE] = [6, 0, 9, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 1];
 
@@ -153,6 +468,38 @@ pub(crate) mod tests {
         assert_eq!(header.sequence_number(), 1);
     }
 
+    pub(crate) fn test_id_version_roundtrip<S: HeaderSerializer, D: HeaderDeserializer>() {
+        let flags = MessageFlags::REQUIRES_ACK | MessageFlags::HAS_PAYLOAD;
+
+        for id in 0..=63 {
+            for version in 0..=3 {
+                let header = Header::new(id, version, flags, 0xABCD, 0x0102_0304_0506_0708);
+                let parsed = D::parse(&S::serialize(&header));
+
+                assert_eq!(parsed, Some(header), "id {id}, version {version}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_try_new() {
+        assert_eq!(
+            Header::try_new(63, 3, MessageFlags::HAS_PAYLOAD, 4, 5).unwrap(),
+            Header::new(63, 3, MessageFlags::HAS_PAYLOAD, 4, 5)
+        );
+        assert!(matches!(
+            Header::try_new(64, 0, MessageFlags::NONE, 0, 0),
+            Err(ProtocolError::FieldOutOfRange { field: "id", .. })
+        ));
+        assert!(matches!(
+            Header::try_new(0, 4, MessageFlags::NONE, 0, 0),
+            Err(ProtocolError::FieldOutOfRange {
+                field: "version",
+                ..
+            })
+        ));
+    }
+
     #[test]
     fn test_to_bytes() {
         let version = 2;
@@ -177,6 +524,302 @@ pub(crate) mod tests {
         assert_eq!(&header_bytes[..], &expected_bytes[..]);
     }
 
+    #[test]
+    fn test_assert_inverse() {
+        assert_inverse::<StandardHeaderParser, StandardHeaderParser>();
+        assert_inverse::<
+            <DefaultHeaderParser as HeaderParser>::Serializer,
+            <DefaultHeaderParser as HeaderParser>::Deserializer,
+        >();
+    }
+
+    #[test]
+    #[should_panic(expected = "round trip")]
+    fn test_assert_inverse_broken_parser() {
+        /// Unpacks the id from the low bits of the first byte, where the version actually lives.
+        struct BrokenParser;
+
+        impl HeaderDeserializer for BrokenParser {
+            fn parse(bytes: &[u8]) -> Option<Header> {
+                let header = StandardHeaderParser::parse(bytes)?;
+
+                Some(Header::new(
+                    bytes[0] & Header::ID_MASK,
+                    header.version(),
+                    header.flags(),
+                    header.payload_len(),
+                    header.sequence_number(),
+                ))
+            }
+        }
+
+        assert_inverse::<StandardHeaderParser, BrokenParser>();
+    }
+
+    #[test]
+    fn test_parse_many() {
+        let headers = [
+            Header::new(1, 0, MessageFlags::NONE, 0, 1),
+            Header::new(2, 1, MessageFlags::HAS_PAYLOAD, 16, 2),
+            Header::new(3, 2, MessageFlags::REQUIRES_ACK, 0, 3),
+        ];
+
+        let mut buf = Vec::new();
+        for header in &headers {
+            buf.extend_from_slice(&header.to_bytes::<StandardHeaderParser>());
+        }
+        buf.extend_from_slice(&[0xAA; 5]);
+
+        let mut bytes = Bytes::from(buf);
+        let mut out = Vec::new();
+
+        assert_eq!(DefaultHeaderParser::parse_many(&mut bytes, &mut out), 3);
+        assert_eq!(out, headers);
+        assert_eq!(&bytes[..], &[0xAA; 5]);
+
+        assert_eq!(DefaultHeaderParser::parse_many(&mut bytes, &mut out), 0);
+        assert_eq!(bytes.len(), 5);
+    }
+
+    #[test]
+    fn test_seq_ordered() {
+        use alloc::collections::BinaryHeap;
+        use core::cmp::Reverse;
+
+        let headers = [
+            Header::new(1, 0, MessageFlags::NONE, 0, 7),
+            Header::new(2, 0, MessageFlags::HAS_PAYLOAD, 16, 3),
+            Header::new(3, 0, MessageFlags::NONE, 0, 5),
+        ];
+
+        let mut sorted = headers.map(SeqOrdered);
+        sorted.sort();
+        assert_eq!(
+            sorted.map(|SeqOrdered(header)| header.sequence_number()),
+            [3, 5, 7]
+        );
+
+        let mut heap: BinaryHeap<_> = headers
+            .into_iter()
+            .map(|h| Reverse(SeqOrdered(h)))
+            .collect();
+        assert_eq!(heap.pop().unwrap().0.0, headers[1]);
+
+        // Only the sequence number counts
+        assert_eq!(
+            SeqOrdered(headers[0]),
+            SeqOrdered(headers[1].with_sequence_number(7))
+        );
+    }
+
+    #[test]
+    fn test_set_clear_flag() {
+        let mut header = Header::new(9, 1, MessageFlags::HAS_PAYLOAD, 512, 4);
+
+        header.set_flag(MessageFlags::ENCRYPTED);
+        assert_eq!(
+            header.flags(),
+            MessageFlags::HAS_PAYLOAD | MessageFlags::ENCRYPTED
+        );
+
+        header.clear_flag(MessageFlags::ENCRYPTED);
+        assert_eq!(header.flags(), MessageFlags::HAS_PAYLOAD);
+
+        assert_eq!(header, Header::new(9, 1, MessageFlags::HAS_PAYLOAD, 512, 4));
+        assert_eq!((header.id(), header.payload_len()), (9, 512));
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let header = Header::new(42, 2, MessageFlags::HAS_PAYLOAD, 1024, 12345);
+
+        assert_eq!(Header::decode(&header.encode()), Some(header));
+        assert_eq!(Header::decode(&header.encode()[..HEADER_SIZE - 1]), None);
+    }
+
+    #[test]
+    fn test_partial_field_reads() {
+        for (id, payload_len) in [(0, 0), (1, 0x200), (17, 0xDEAD_BEEF), (63, u32::MAX)] {
+            let header = Header::new(id, 3, MessageFlags::HAS_PAYLOAD, payload_len, 42);
+            let bytes = header.to_bytes::<StandardHeaderParser>();
+            let parsed = Header::parse::<StandardHeaderParser>(&bytes).unwrap();
+
+            assert_eq!(id_only(&bytes), Some(parsed.id()));
+            assert_eq!(payload_len_only(&bytes), Some(parsed.payload_len()));
+        }
+
+        assert_eq!(id_only(&HEADER_BYTES[..HEADER_SIZE - 1]), None);
+        assert_eq!(payload_len_only(&[]), None);
+    }
+
+    #[test]
+    fn test_parse_lenient() {
+        assert_eq!(parse_lenient(&[]), PartialHeader::default());
+
+        let one = parse_lenient(&HEADER_BYTES[..1]);
+        assert_eq!((one.id, one.version), (Some(1), Some(2)));
+        assert_eq!(one.flags, None);
+        assert_eq!(one.payload_len, None);
+
+        let three = parse_lenient(&HEADER_BYTES[..3]);
+        assert_eq!(three.flags, Some(MessageFlags::from(9)));
+        assert_eq!(three.payload_len, None);
+
+        let seven = parse_lenient(&HEADER_BYTES[..7]);
+        assert_eq!(seven.payload_len, Some(0x200));
+        assert_eq!(seven.sequence_number, None);
+        assert!(!seven.is_complete());
+
+        let full = parse_lenient(&HEADER_BYTES);
+        assert_eq!(
+            full.to_header(),
+            Header::parse::<StandardHeaderParser>(&HEADER_BYTES)
+        );
+    }
+
+    #[test]
+    fn test_serialize_into() {
+        let header = Header::new(1, 2, MessageFlags::HAS_PAYLOAD, 0x200, 1);
+
+        let mut buf = [0xAA; HEADER_SIZE + 8];
+        header
+            .serialize_into::<StandardHeaderParser>(&mut buf)
+            .unwrap();
+
+        assert_eq!(
+            buf[..HEADER_SIZE],
+            header.to_bytes::<StandardHeaderParser>()
+        );
+        assert!(buf[HEADER_SIZE..].iter().all(|&byte| byte == 0xAA));
+
+        #[cfg(feature = "std")]
+        {
+            let mut default_buf = [0xAA; HEADER_SIZE + 8];
+            header
+                .serialize_into::<crate::header::runtime::RuntimeHeaderParser>(&mut default_buf)
+                .unwrap();
+            assert_eq!(default_buf, buf);
+        }
+
+        let mut short = [0u8; HEADER_SIZE - 1];
+        assert!(matches!(
+            header.serialize_into::<StandardHeaderParser>(&mut short),
+            Err(crate::error::ProtocolError::BufferTooSmall {
+                needed: HEADER_SIZE,
+                got: 14
+            })
+        ));
+    }
+
+    proptest! {
+        #[test]
+        fn test_parsers_on_random_lengths(
+            buf in prop::collection::vec(any::<u8>(), 0..32)
+        ) {
+            use crate::header::optimized::OptimizedHeaderParser;
+            #[cfg(feature = "std")]
+            use crate::header::runtime::RuntimeHeaderParser;
+
+            let len = buf.len();
+            let standard = StandardHeaderParser::parse(&buf);
+            let optimized = OptimizedHeaderParser::parse(&buf);
+            #[cfg(feature = "std")]
+            prop_assert_eq!(RuntimeHeaderParser::parse(&buf), standard);
+
+            if len < HEADER_SIZE {
+                prop_assert_eq!(standard, None);
+                prop_assert_eq!(optimized, None);
+            } else {
+                prop_assert!(standard.is_some());
+                prop_assert_eq!(optimized, standard);
+            }
+
+            // Short input is left untouched, anything else loses exactly one header
+            let mut bytes = Bytes::from(buf);
+            let parsed = StandardHeaderParser::parse_bytes(&mut bytes);
+            prop_assert_eq!(parsed.is_some(), len >= HEADER_SIZE);
+            let consumed = if parsed.is_some() { HEADER_SIZE } else { 0 };
+            prop_assert_eq!(bytes.len(), len - consumed);
+        }
+    }
+
+    #[test]
+    fn test_serialize_to_buf() {
+        fn check<S: HeaderSerializer>(header: &Header) {
+            let mut buf = bytes::BytesMut::from(&b"prefix"[..]);
+            S::serialize_to_buf(header, &mut buf);
+
+            assert_eq!(&buf[..6], b"prefix");
+            assert_eq!(buf[6..], S::serialize(header));
+        }
+
+        let header = Header::new(
+            3,
+            1,
+            MessageFlags::REQUIRES_ACK | MessageFlags::HAS_PAYLOAD,
+            0x0102_0304,
+            0x0102_0304_0506_0708,
+        );
+
+        check::<StandardHeaderParser>(&header);
+        check::<little_endian::LittleEndianHeaderParser>(&header);
+        #[cfg(feature = "std")]
+        check::<runtime::RuntimeHeaderParser>(&header);
+    }
+
+    #[test]
+    fn test_with_methods() {
+        let header = Header::new(
+            3,
+            1,
+            MessageFlags::REQUIRES_ACK | MessageFlags::HAS_PAYLOAD,
+            42,
+            7,
+        );
+
+        let echoed = header.with_flags(header.flags() & !MessageFlags::REQUIRES_ACK);
+        assert_eq!(echoed.flags(), MessageFlags::HAS_PAYLOAD);
+        assert_eq!(
+            (
+                echoed.id(),
+                echoed.version(),
+                echoed.payload_len(),
+                echoed.sequence_number()
+            ),
+            (3, 1, 42, 7)
+        );
+
+        let updated = header
+            .with_id(4)
+            .with_version(2)
+            .with_payload_len(0)
+            .with_sequence_number(8);
+        assert_eq!(updated, Header::new(4, 2, header.flags(), 0, 8));
+        assert_eq!(header.with_id(3), header);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_json_roundtrip() {
+        let header = Header::new(
+            3,
+            1,
+            MessageFlags::REQUIRES_ACK | MessageFlags::HAS_PAYLOAD,
+            42,
+            7,
+        );
+        let json = serde_json::to_string(&header).unwrap();
+
+        assert_eq!(
+            json,
+            r#"{"id":3,"version":1,"flags":["REQUIRES_ACK","HAS_PAYLOAD"],"payload_len":42,"sequence_number":7}"#
+        );
+        assert_eq!(serde_json::from_str::<Header>(&json).unwrap(), header);
+
+        let out_of_range = json.replace(r#""version":1"#, r#""version":200"#);
+        assert!(serde_json::from_str::<Header>(&out_of_range).is_err());
+    }
+
     #[test]
     fn test_roundtrip() {
         let version = 2;
@@ -188,7 +831,7 @@ pub(crate) mod tests {
         let original_header = Header::new(id, version, flags, payload_len, sequence_number);
         let bytes = original_header.to_bytes::<StandardHeaderParser>();
         let recovered_header =
-            Header::parse::<StandardHeaderParser>(&mut Bytes::from_owner(bytes)).unwrap();
+            Header::parse::<StandardHeaderParser>(&Bytes::from_owner(bytes)).unwrap();
 
         assert_eq!(recovered_header.id, id);
         assert_eq!(recovered_header.version, version);
@@ -1,6 +1,7 @@
-use crate::constants::HEADER_SIZE;
+use crate::constants::{FLAGS_OFFSET, HEADER_SIZE, PAYLOAD_OFFSET, SEQ_OFFSET};
 use crate::header::Header;
 use crate::message_flags::MessageFlags;
+use crate::protocol_params::VERSION_BITS;
 use crate::traits::header::HeaderDeserializer;
 
 pub struct OptimizedHeaderParser;
@@ -12,32 +13,27 @@ impl HeaderDeserializer for OptimizedHeaderParser {
             return None;
         }
 
+        // SAFETY: the check above guarantees `HEADER_SIZE` readable bytes at `buf.as_ptr()`, and
+        // `read_unaligned` has no alignment requirement. The assertion guards that invariant
+        // should the check ever be moved or changed.
         unsafe {
-            let header_bytes = std::ptr::read_unaligned(buf.as_ptr() as *const [u8; HEADER_SIZE]);
+            debug_assert!(
+                buf.len() >= HEADER_SIZE,
+                "header parser read past the buffer"
+            );
+
+            let header_bytes = core::ptr::read_unaligned(buf.as_ptr() as *const [u8; HEADER_SIZE]);
 
             let first_byte = header_bytes[0];
-            let id = (first_byte & Header::LAST_SIX_BITS) >> 2;
-            let version = first_byte & Header::LAST_TWO_BITS;
-
-            let flags = u16::from_be_bytes([header_bytes[1], header_bytes[2]]);
-
-            let payload_len = u32::from_be_bytes([
-                header_bytes[3],
-                header_bytes[4],
-                header_bytes[5],
-                header_bytes[6],
-            ]);
-
-            let sequence_number = u64::from_be_bytes([
-                header_bytes[7],
-                header_bytes[8],
-                header_bytes[9],
-                header_bytes[10],
-                header_bytes[11],
-                header_bytes[12],
-                header_bytes[13],
-                header_bytes[14],
-            ]);
+            let id = first_byte >> VERSION_BITS;
+            let version = first_byte & Header::VERSION_MASK;
+
+            let flags =
+                u16::from_be_bytes(header_bytes[FLAGS_OFFSET..PAYLOAD_OFFSET].try_into().ok()?);
+            let payload_len =
+                u32::from_be_bytes(header_bytes[PAYLOAD_OFFSET..SEQ_OFFSET].try_into().ok()?);
+            let sequence_number =
+                u64::from_be_bytes(header_bytes[SEQ_OFFSET..HEADER_SIZE].try_into().ok()?);
 
             Some(Header::new(
                 id,
@@ -53,10 +49,16 @@ impl HeaderDeserializer for OptimizedHeaderParser {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::header::tests::test_deserializer;
+    use crate::header::standard::StandardHeaderParser;
+    use crate::header::tests::{test_deserializer, test_id_version_roundtrip};
 
     #[test]
     fn test_optimized_deserializer() {
         test_deserializer::<OptimizedHeaderParser>()
     }
+
+    #[test]
+    fn test_optimized_id_version_roundtrip() {
+        test_id_version_roundtrip::<StandardHeaderParser, OptimizedHeaderParser>()
+    }
 }
@@ -0,0 +1,132 @@
+use crate::constants::HEADER_SIZE;
+use crate::header::Header;
+use crate::header::optimized::OptimizedHeaderParser;
+use crate::header::standard::StandardHeaderParser;
+use crate::traits::header::{HeaderDeserializer, HeaderParser, HeaderSerializer};
+use core::sync::atomic::{AtomicU8, Ordering};
+
+const UNDETECTED: u8 = 0;
+#[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+const FALLBACK: u8 = 1;
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+const SSE2: u8 = 2;
+#[cfg(all(feature = "simd", target_arch = "aarch64"))]
+const NEON: u8 = 3;
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+const AVX2: u8 = 4;
+
+static IMPLEMENTATION: AtomicU8 = AtomicU8::new(UNDETECTED);
+
+/// Parser that picks the fastest implementation available on the CPU it's running on, rather than
+/// the one the binary was compiled for.
+///
+/// CPU features are detected once, on first use, and cached. On x86_64 the AVX2 implementation is
+/// used when the CPU has it, and the SSE2 one otherwise, since SSE2 is part of the baseline.
+/// Without SIMD support (or without the `simd` feature) it falls back to [`OptimizedHeaderParser`]
+/// for parsing and [`StandardHeaderParser`] for serializing.
+pub struct RuntimeHeaderParser;
+
+impl RuntimeHeaderParser {
+    #[inline(always)]
+    fn implementation() -> u8 {
+        match IMPLEMENTATION.load(Ordering::Relaxed) {
+            UNDETECTED => {
+                let detected = Self::detect();
+                IMPLEMENTATION.store(detected, Ordering::Relaxed);
+
+                detected
+            }
+            detected => detected,
+        }
+    }
+
+    #[cold]
+    fn detect() -> u8 {
+        // SSE2 is part of the x86_64 baseline, so it only needs to be picked when AVX2 is missing
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        return if std::is_x86_feature_detected!("avx2") {
+            AVX2
+        } else {
+            SSE2
+        };
+
+        #[cfg(all(feature = "simd", target_arch = "aarch64"))]
+        if std::arch::is_aarch64_feature_detected!("neon") {
+            return NEON;
+        }
+
+        #[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+        return FALLBACK;
+    }
+}
+
+impl HeaderParser for RuntimeHeaderParser {
+    type Serializer = Self;
+    type Deserializer = Self;
+}
+
+impl HeaderSerializer for RuntimeHeaderParser {
+    #[inline]
+    fn serialize(header: &Header) -> [u8; HEADER_SIZE] {
+        match Self::implementation() {
+            // SAFETY: AVX2 support was detected at runtime.
+            #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+            AVX2 => unsafe { crate::header::simd::avx2::serialize(header) },
+            // SAFETY: SSE2 is always available on x86_64.
+            #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+            SSE2 => unsafe { crate::header::simd::sse2::serialize(header) },
+            // SAFETY: NEON support was detected at runtime.
+            #[cfg(all(feature = "simd", target_arch = "aarch64"))]
+            NEON => unsafe { crate::header::simd::neon::serialize(header) },
+            _ => StandardHeaderParser::serialize(header),
+        }
+    }
+}
+
+impl HeaderDeserializer for RuntimeHeaderParser {
+    #[inline]
+    fn parse(bytes: &[u8]) -> Option<Header> {
+        match Self::implementation() {
+            // SAFETY: AVX2 support was detected at runtime.
+            #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+            AVX2 => unsafe { crate::header::simd::avx2::parse(bytes) },
+            // SAFETY: SSE2 is always available on x86_64.
+            #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+            SSE2 => unsafe { crate::header::simd::sse2::parse(bytes) },
+            // SAFETY: NEON support was detected at runtime.
+            #[cfg(all(feature = "simd", target_arch = "aarch64"))]
+            NEON => unsafe { crate::header::simd::neon::parse(bytes) },
+            _ => OptimizedHeaderParser::parse(bytes),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::tests::{test_deserializer, test_id_version_roundtrip, test_serializer};
+
+    #[test]
+    fn test_runtime_serializer() {
+        test_serializer::<RuntimeHeaderParser>()
+    }
+
+    #[test]
+    fn test_runtime_deserializer() {
+        test_deserializer::<RuntimeHeaderParser>()
+    }
+
+    #[test]
+    fn test_runtime_id_version_roundtrip() {
+        test_id_version_roundtrip::<RuntimeHeaderParser, RuntimeHeaderParser>()
+    }
+
+    #[test]
+    fn test_runtime_detection_is_cached() {
+        let first = RuntimeHeaderParser::implementation();
+
+        assert_ne!(first, UNDETECTED);
+        assert_eq!(IMPLEMENTATION.load(Ordering::Relaxed), first);
+        assert_eq!(RuntimeHeaderParser::implementation(), first);
+    }
+}
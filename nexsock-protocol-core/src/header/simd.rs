@@ -1,12 +1,266 @@
 #![cfg(feature = "simd")]
 #![allow(unsafe_code)]
 
-use crate::constants::HEADER_SIZE;
-use crate::header::Header;
-use crate::message_flags::MessageFlags;
+#[cfg(any(
+    all(target_arch = "aarch64", target_feature = "neon"),
+    all(target_arch = "x86_64", target_feature = "sse2")
+))]
 use crate::traits::header::{HeaderDeserializer, HeaderSerializer};
-use bytes::{Buf, Bytes};
-use std::simd::SupportedLaneCount;
+#[cfg(any(target_arch = "aarch64", target_arch = "x86_64"))]
+use crate::{
+    constants::{FLAGS_OFFSET, HEADER_SIZE, PAYLOAD_OFFSET, SEQ_OFFSET},
+    header::Header,
+    message_flags::MessageFlags,
+    protocol_params::VERSION_BITS,
+};
+
+#[cfg(any(target_arch = "aarch64", target_arch = "x86_64"))]
+const _: () = assert!(
+    HEADER_SIZE <= 16,
+    "the header has to fit in one vector register"
+);
+
+/// Packs `header` into a full 16-byte block, so vector loads and stores never touch memory past
+/// the end of a 15-byte header.
+#[cfg(any(target_arch = "aarch64", target_arch = "x86_64"))]
+#[inline(always)]
+fn pack(header: &Header) -> [u8; 16] {
+    let mut block = [0u8; 16];
+
+    block[0] =
+        ((header.id & Header::ID_MASK) << VERSION_BITS) | (header.version & Header::VERSION_MASK);
+    block[FLAGS_OFFSET..PAYLOAD_OFFSET].copy_from_slice(&(*header.flags).to_be_bytes());
+    block[PAYLOAD_OFFSET..SEQ_OFFSET].copy_from_slice(&header.payload_len.to_be_bytes());
+    block[SEQ_OFFSET..HEADER_SIZE].copy_from_slice(&header.sequence_number.to_be_bytes());
+
+    block
+}
+
+/// Copies the first header of `buf` into a 16-byte block, padding when `buf` holds exactly one
+/// header. `buf` must be at least [`HEADER_SIZE`] bytes long.
+#[cfg(any(target_arch = "aarch64", target_arch = "x86_64"))]
+#[inline(always)]
+fn padded(buf: &[u8]) -> [u8; 16] {
+    let mut block = [0u8; 16];
+    block[..HEADER_SIZE].copy_from_slice(&buf[..HEADER_SIZE]);
+
+    block
+}
+
+#[cfg(any(target_arch = "aarch64", target_arch = "x86_64"))]
+#[inline(always)]
+fn unpack(block: &[u8; 16]) -> Header {
+    let first_byte = block[0];
+    let id = first_byte >> VERSION_BITS;
+    let version = first_byte & Header::VERSION_MASK;
+
+    let flags = u16::from_be_bytes(block[FLAGS_OFFSET..PAYLOAD_OFFSET].try_into().unwrap());
+
+    let payload_len = u32::from_be_bytes(block[PAYLOAD_OFFSET..SEQ_OFFSET].try_into().unwrap());
+
+    let sequence_number = u64::from_be_bytes(block[SEQ_OFFSET..HEADER_SIZE].try_into().unwrap());
+
+    Header::new(
+        id,
+        version,
+        MessageFlags::from(flags),
+        payload_len,
+        sequence_number,
+    )
+}
+
+#[cfg(any(target_arch = "aarch64", target_arch = "x86_64"))]
+#[inline(always)]
+fn truncate(block: &[u8; 16]) -> [u8; HEADER_SIZE] {
+    let mut buffer = [0u8; HEADER_SIZE];
+    buffer.copy_from_slice(&block[..HEADER_SIZE]);
+
+    buffer
+}
+
+/// NEON kernels. These are compiled regardless of the target features the crate is built with,
+/// so callers must make sure NEON is available, either at compile time or at runtime.
+#[cfg(target_arch = "aarch64")]
+pub(crate) mod neon {
+    use super::*;
+    use core::arch::aarch64::*;
+
+    /// # Safety
+    ///
+    /// The CPU must support NEON.
+    #[target_feature(enable = "neon")]
+    pub(crate) unsafe fn serialize(header: &Header) -> [u8; HEADER_SIZE] {
+        // Fill a 128-bit NEON vector with our data so we can do a single write at the end
+        let block = pack(header);
+        let mut out = [0u8; 16];
+
+        unsafe {
+            let neon_data = vld1q_u8(block.as_ptr());
+            vst1q_u8(out.as_mut_ptr(), neon_data);
+        }
+
+        truncate(&out)
+    }
+
+    /// # Safety
+    ///
+    /// The CPU must support NEON.
+    #[target_feature(enable = "neon")]
+    pub(crate) unsafe fn parse(buf: &[u8]) -> Option<Header> {
+        if buf.len() < HEADER_SIZE {
+            return None;
+        }
+
+        let mut block = [0u8; 16];
+
+        unsafe {
+            let neon_data = if buf.len() >= 16 {
+                vld1q_u8(buf.as_ptr())
+            } else {
+                vld1q_u8(padded(buf).as_ptr())
+            };
+
+            vst1q_u8(block.as_mut_ptr(), neon_data);
+        }
+
+        Some(unpack(&block))
+    }
+}
+
+/// SSE2 kernels. These are compiled regardless of the target features the crate is built with,
+/// so callers must make sure SSE2 is available, either at compile time or at runtime.
+#[cfg(target_arch = "x86_64")]
+pub(crate) mod sse2 {
+    use super::*;
+    use core::arch::x86_64::*;
+
+    /// # Safety
+    ///
+    /// The CPU must support SSE2.
+    #[target_feature(enable = "sse2")]
+    pub(crate) unsafe fn serialize(header: &Header) -> [u8; HEADER_SIZE] {
+        let block = pack(header);
+        let mut out = [0u8; 16];
+
+        unsafe {
+            let data = _mm_loadu_si128(block.as_ptr() as *const __m128i);
+            _mm_storeu_si128(out.as_mut_ptr() as *mut __m128i, data);
+        }
+
+        truncate(&out)
+    }
+
+    /// # Safety
+    ///
+    /// The CPU must support SSE2.
+    #[target_feature(enable = "sse2")]
+    pub(crate) unsafe fn parse(buf: &[u8]) -> Option<Header> {
+        if buf.len() < HEADER_SIZE {
+            return None;
+        }
+
+        let mut block = [0u8; 16];
+
+        unsafe {
+            let data = if buf.len() >= 16 {
+                _mm_loadu_si128(buf.as_ptr() as *const __m128i)
+            } else {
+                _mm_loadu_si128(padded(buf).as_ptr() as *const __m128i)
+            };
+
+            _mm_storeu_si128(block.as_mut_ptr() as *mut __m128i, data);
+        }
+
+        Some(unpack(&block))
+    }
+}
+
+/// AVX2 kernels. AVX2 implies SSSE3, whose byte shuffle swaps every big-endian field into native
+/// order in one instruction, instead of the SSE2 kernels' scalar conversions. These are compiled
+/// regardless of the target features the crate is built with, so callers must check for AVX2 at
+/// runtime.
+#[cfg(target_arch = "x86_64")]
+pub(crate) mod avx2 {
+    use super::*;
+    use core::arch::x86_64::*;
+
+    /// Shuffle control that reverses the bytes of every multi-byte field and clears the padding
+    /// byte. Swapping twice is a no-op, so the same mask goes both ways.
+    const SWAP_FIELDS: [u8; 16] = {
+        const fn reverse(mut mask: [u8; 16], start: usize, end: usize) -> [u8; 16] {
+            let mut i = start;
+            while i < end {
+                mask[i] = (start + end - 1 - i) as u8;
+                i += 1;
+            }
+
+            mask
+        }
+
+        // Any index with the high bit set makes the shuffle write a zero
+        let mut mask = [0x80; 16];
+        mask[0] = 0;
+        mask = reverse(mask, FLAGS_OFFSET, PAYLOAD_OFFSET);
+        mask = reverse(mask, PAYLOAD_OFFSET, SEQ_OFFSET);
+        reverse(mask, SEQ_OFFSET, HEADER_SIZE)
+    };
+
+    #[target_feature(enable = "avx2")]
+    #[inline]
+    fn swap_fields(block: &[u8; 16]) -> [u8; 16] {
+        let mut out = [0u8; 16];
+
+        // SAFETY: both pointers cover 16 bytes, and the loads and stores are unaligned.
+        unsafe {
+            let data = _mm_loadu_si128(block.as_ptr() as *const __m128i);
+            let mask = _mm_loadu_si128(SWAP_FIELDS.as_ptr() as *const __m128i);
+            _mm_storeu_si128(
+                out.as_mut_ptr() as *mut __m128i,
+                _mm_shuffle_epi8(data, mask),
+            );
+        }
+
+        out
+    }
+
+    /// # Safety
+    ///
+    /// The CPU must support AVX2.
+    #[target_feature(enable = "avx2")]
+    pub(crate) unsafe fn serialize(header: &Header) -> [u8; HEADER_SIZE] {
+        let mut block = [0u8; 16];
+
+        block[0] = ((header.id & Header::ID_MASK) << VERSION_BITS)
+            | (header.version & Header::VERSION_MASK);
+        block[FLAGS_OFFSET..PAYLOAD_OFFSET].copy_from_slice(&(*header.flags).to_ne_bytes());
+        block[PAYLOAD_OFFSET..SEQ_OFFSET].copy_from_slice(&header.payload_len.to_ne_bytes());
+        block[SEQ_OFFSET..HEADER_SIZE].copy_from_slice(&header.sequence_number.to_ne_bytes());
+
+        truncate(&swap_fields(&block))
+    }
+
+    /// # Safety
+    ///
+    /// The CPU must support AVX2.
+    #[target_feature(enable = "avx2")]
+    pub(crate) unsafe fn parse(buf: &[u8]) -> Option<Header> {
+        if buf.len() < HEADER_SIZE {
+            return None;
+        }
+
+        let block = swap_fields(&padded(buf));
+
+        Some(Header::new(
+            block[0] >> VERSION_BITS,
+            block[0] & Header::VERSION_MASK,
+            MessageFlags::from(u16::from_ne_bytes(
+                block[FLAGS_OFFSET..PAYLOAD_OFFSET].try_into().unwrap(),
+            )),
+            u32::from_ne_bytes(block[PAYLOAD_OFFSET..SEQ_OFFSET].try_into().unwrap()),
+            u64::from_ne_bytes(block[SEQ_OFFSET..HEADER_SIZE].try_into().unwrap()),
+        ))
+    }
+}
 
 /// A heavily optimized parser for `aarch64` targets, it's not recommended you need to get every last bit of performance
 /// by leveraging aarch64 neon.
@@ -17,100 +271,107 @@ pub struct Aarch64NeonHeaderParser;
 impl HeaderSerializer for Aarch64NeonHeaderParser {
     #[inline]
     fn serialize(header: &Header) -> [u8; HEADER_SIZE] {
-        use std::arch::aarch64::*;
+        // SAFETY: NEON is enabled at compile time for this target.
+        unsafe { neon::serialize(header) }
+    }
+}
 
-        unsafe {
-            // First create a 128-bit NEON vector and fill it with our data
-            // This allows us to do a single write at the end
-            let mut tmp_buf = [0u8; 16]; // Use 16 bytes for alignment
+#[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
+impl HeaderDeserializer for Aarch64NeonHeaderParser {
+    #[inline]
+    fn parse(buf: &[u8]) -> Option<Header> {
+        // SAFETY: NEON is enabled at compile time for this target.
+        unsafe { neon::parse(buf) }
+    }
+}
 
-            // Pack fields with proper endianness
-            tmp_buf[0] = ((header.id & Header::LAST_SIX_BITS) << 2)
-                | (header.version & Header::LAST_TWO_BITS);
+/// SSE2 based parser for `x86_64` targets, moving the header in and out of a single 128-bit
+/// register the same way [`Aarch64NeonHeaderParser`] does on aarch64.
+#[cfg(all(target_arch = "x86_64", target_feature = "sse2"))]
+pub struct X86SimdHeaderParser;
 
-            let flags_be = (*header.flags).to_be();
-            std::ptr::write_unaligned(tmp_buf.as_mut_ptr().add(1) as *mut u16, flags_be);
+#[cfg(all(target_arch = "x86_64", target_feature = "sse2"))]
+impl HeaderSerializer for X86SimdHeaderParser {
+    #[inline]
+    fn serialize(header: &Header) -> [u8; HEADER_SIZE] {
+        // SAFETY: SSE2 is enabled at compile time for this target.
+        unsafe { sse2::serialize(header) }
+    }
+}
 
-            let payload_be = header.payload_len.to_be();
-            std::ptr::write_unaligned(tmp_buf.as_mut_ptr().add(3) as *mut u32, payload_be);
+#[cfg(all(target_arch = "x86_64", target_feature = "sse2"))]
+impl HeaderDeserializer for X86SimdHeaderParser {
+    #[inline]
+    fn parse(buf: &[u8]) -> Option<Header> {
+        // SAFETY: SSE2 is enabled at compile time for this target.
+        unsafe { sse2::parse(buf) }
+    }
+}
 
-            let seq_be = header.sequence_number.to_be();
-            std::ptr::write_unaligned(tmp_buf.as_mut_ptr().add(7) as *mut u64, seq_be);
+#[cfg(all(test, target_arch = "x86_64", target_feature = "sse2"))]
+mod x86_tests {
+    use super::*;
+    use crate::header::tests::{test_deserializer, test_id_version_roundtrip, test_serializer};
 
-            // Load the prepared data into a NEON register
-            let neon_data = vld1q_u8(tmp_buf.as_ptr());
+    #[test]
+    fn test_x86_simd_serialize() {
+        test_serializer::<X86SimdHeaderParser>()
+    }
 
-            // Write to final buffer in one NEON operation
-            let mut buffer = std::mem::MaybeUninit::<[u8; HEADER_SIZE]>::uninit();
-            vst1q_u8(buffer.as_mut_ptr() as *mut u8, neon_data);
+    #[test]
+    fn test_x86_simd_deserialize() {
+        test_deserializer::<X86SimdHeaderParser>()
+    }
 
-            buffer.assume_init()
-        }
+    #[test]
+    fn test_x86_simd_id_version_roundtrip() {
+        test_id_version_roundtrip::<X86SimdHeaderParser, X86SimdHeaderParser>()
     }
-}
 
-#[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
-impl HeaderDeserializer for Aarch64NeonHeaderParser {
-    #[inline]
-    fn parse(buf: &[u8]) -> Option<Header> {
-        use std::arch::aarch64::*;
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_avx2_matches_standard() {
+        use crate::header::standard::StandardHeaderParser;
 
-        if buf.len() < HEADER_SIZE {
-            return None;
+        if !std::is_x86_feature_detected!("avx2") {
+            return;
         }
 
+        let header = Header::new(
+            5,
+            2,
+            MessageFlags::REQUIRES_ACK | MessageFlags::HAS_PAYLOAD,
+            0x0102_0304,
+            0x0506_0708_090A_0B0C,
+        );
+        let bytes = StandardHeaderParser::serialize(&header);
+
+        // SAFETY: AVX2 support was checked above.
         unsafe {
-            let neon_data = vld1q_u8(buf.as_ptr());
-
-            let mut tmp_buf = [0u8; HEADER_SIZE];
-            vst1q_u8(tmp_buf.as_mut_ptr(), neon_data);
-
-            let first_byte = tmp_buf[0];
-            let id = (first_byte >> 2) & Header::LAST_SIX_BITS;
-            let version = first_byte & Header::LAST_TWO_BITS;
-
-            let flags = u16::from_be_bytes([tmp_buf[1], tmp_buf[2]]);
-
-            let payload_len = u32::from_be_bytes([tmp_buf[3], tmp_buf[4], tmp_buf[5], tmp_buf[6]]);
-
-            let sequence_number = u64::from_be_bytes([
-                tmp_buf[7],
-                tmp_buf[8],
-                tmp_buf[9],
-                tmp_buf[10],
-                tmp_buf[11],
-                tmp_buf[12],
-                tmp_buf[13],
-                tmp_buf[14],
-            ]);
-
-            Some(Header::new(
-                id,
-                version,
-                MessageFlags::from(flags),
-                payload_len,
-                sequence_number,
-            ))
+            assert_eq!(avx2::serialize(&header), bytes);
+            assert_eq!(avx2::parse(&bytes), Some(header));
+            assert_eq!(avx2::parse(&bytes[..HEADER_SIZE - 1]), None);
         }
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, target_arch = "aarch64", target_feature = "neon"))]
 mod tests {
     use super::*;
-    use crate::header::optimized::OptimizedHeaderParser;
-    use crate::header::standard::StandardHeaderParser;
-    use crate::header::tests::{test_deserializer, test_serializer};
+    use crate::header::tests::{test_deserializer, test_id_version_roundtrip, test_serializer};
 
     #[test]
-    #[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
     fn test_aarch64_neon_serialize() {
         test_serializer::<Aarch64NeonHeaderParser>()
     }
 
     #[test]
-    #[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
     fn test_aarch64_neon_deserialize() {
         test_deserializer::<Aarch64NeonHeaderParser>()
     }
+
+    #[test]
+    fn test_aarch64_neon_id_version_roundtrip() {
+        test_id_version_roundtrip::<Aarch64NeonHeaderParser, Aarch64NeonHeaderParser>()
+    }
 }
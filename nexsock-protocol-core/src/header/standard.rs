@@ -1,11 +1,36 @@
+use crate::error::{ProtocolError, ProtocolResult};
+use crate::protocol_params::VERSION_BITS;
 use crate::traits::header::HeaderSerializer;
 use crate::{
-    constants::HEADER_SIZE, header::Header, message_flags::MessageFlags,
+    constants::{FLAGS_OFFSET, HEADER_SIZE, PAYLOAD_OFFSET, SEQ_OFFSET},
+    header::Header,
+    message_flags::MessageFlags,
     traits::header::HeaderDeserializer,
 };
+use bytes::{BufMut, BytesMut};
 
+/// The reference implementation of the standard header layout. Every other parser of that layout
+/// has to agree with it byte for byte, which `tests/parser_differential.rs` checks on random
+/// headers.
 pub struct StandardHeaderParser;
 
+impl StandardHeaderParser {
+    /// Like [`HeaderDeserializer::parse`], but rejects headers with flag bits that aren't defined
+    /// by [`MessageFlags`] instead of carrying them along.
+    pub fn parse_strict(bytes: &[u8]) -> ProtocolResult<Option<Header>> {
+        let Some(header) = Self::parse(bytes) else {
+            return Ok(None);
+        };
+
+        match MessageFlags::from_bits_checked(*header.flags()) {
+            Some(_) => Ok(Some(header)),
+            None => Err(ProtocolError::UnknownFlags {
+                bits: *header.flags(),
+            }),
+        }
+    }
+}
+
 impl HeaderDeserializer for StandardHeaderParser {
     #[inline]
     fn parse(bytes: &[u8]) -> Option<Header> {
@@ -14,24 +39,12 @@ impl HeaderDeserializer for StandardHeaderParser {
         }
 
         let id_version = bytes[0];
-        let id = (id_version & Header::LAST_SIX_BITS) >> 2;
-        let version = id_version & Header::LAST_TWO_BITS;
-
-        let flags = ((bytes[1] as u16) << 8) | (bytes[2] as u16);
-
-        let payload_len = ((bytes[3] as u32) << 24)
-            | ((bytes[4] as u32) << 16)
-            | ((bytes[5] as u32) << 8)
-            | (bytes[6] as u32);
+        let id = id_version >> VERSION_BITS;
+        let version = id_version & Header::VERSION_MASK;
 
-        let sequence_number = ((bytes[7] as u64) << 56)
-            | ((bytes[8] as u64) << 48)
-            | ((bytes[9] as u64) << 40)
-            | ((bytes[10] as u64) << 32)
-            | ((bytes[11] as u64) << 24)
-            | ((bytes[12] as u64) << 16)
-            | ((bytes[13] as u64) << 8)
-            | (bytes[14] as u64);
+        let flags = u16::from_be_bytes(bytes[FLAGS_OFFSET..PAYLOAD_OFFSET].try_into().ok()?);
+        let payload_len = u32::from_be_bytes(bytes[PAYLOAD_OFFSET..SEQ_OFFSET].try_into().ok()?);
+        let sequence_number = u64::from_be_bytes(bytes[SEQ_OFFSET..HEADER_SIZE].try_into().ok()?);
 
         Some(Header::new(
             id,
@@ -48,37 +61,67 @@ impl HeaderSerializer for StandardHeaderParser {
     fn serialize(header: &Header) -> [u8; HEADER_SIZE] {
         unsafe {
             // Initialize buffer without zero-initialization overhead
-            let mut buffer = std::mem::MaybeUninit::<[u8; HEADER_SIZE]>::uninit();
+            let mut buffer = core::mem::MaybeUninit::<[u8; HEADER_SIZE]>::uninit();
             let buf_ptr = buffer.as_mut_ptr() as *mut u8;
 
             // First byte: id and version packed together
-            *buf_ptr = ((header.id & Header::LAST_SIX_BITS) << 2)
-                | (header.version & Header::LAST_TWO_BITS);
+            *buf_ptr = ((header.id & Header::ID_MASK) << VERSION_BITS)
+                | (header.version & Header::VERSION_MASK);
 
             // For maximum performance on modern CPUs, use direct unaligned writes
             // instead of manual byte manipulation + SIMD operations
 
             // Write flags (2 bytes) directly as a single u16
             let flags_be = (*header.flags).to_be();
-            std::ptr::write_unaligned(buf_ptr.add(1) as *mut u16, flags_be);
+            core::ptr::write_unaligned(buf_ptr.add(FLAGS_OFFSET) as *mut u16, flags_be);
 
             // Write payload length (4 bytes) directly as a single u32
             let payload_be = header.payload_len.to_be();
-            std::ptr::write_unaligned(buf_ptr.add(3) as *mut u32, payload_be);
+            core::ptr::write_unaligned(buf_ptr.add(PAYLOAD_OFFSET) as *mut u32, payload_be);
 
             // Write sequence number (8 bytes) directly as a single u64
             let seq_be = header.sequence_number.to_be();
-            std::ptr::write_unaligned(buf_ptr.add(7) as *mut u64, seq_be);
+            core::ptr::write_unaligned(buf_ptr.add(SEQ_OFFSET) as *mut u64, seq_be);
 
             buffer.assume_init()
         }
     }
+
+    #[inline]
+    fn serialize_into(header: &Header, dst: &mut [u8]) -> ProtocolResult<()> {
+        if dst.len() < HEADER_SIZE {
+            return Err(ProtocolError::BufferTooSmall {
+                needed: HEADER_SIZE,
+                got: dst.len(),
+            });
+        }
+
+        dst[0] = ((header.id & Header::ID_MASK) << VERSION_BITS)
+            | (header.version & Header::VERSION_MASK);
+        dst[FLAGS_OFFSET..PAYLOAD_OFFSET].copy_from_slice(&(*header.flags).to_be_bytes());
+        dst[PAYLOAD_OFFSET..SEQ_OFFSET].copy_from_slice(&header.payload_len.to_be_bytes());
+        dst[SEQ_OFFSET..HEADER_SIZE].copy_from_slice(&header.sequence_number.to_be_bytes());
+
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_to_buf(header: &Header, buf: &mut BytesMut) {
+        buf.reserve(HEADER_SIZE);
+        buf.put_u8(
+            ((header.id & Header::ID_MASK) << VERSION_BITS)
+                | (header.version & Header::VERSION_MASK),
+        );
+        buf.put_u16(*header.flags);
+        buf.put_u32(header.payload_len);
+        buf.put_u64(header.sequence_number);
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::header::tests::{test_deserializer, test_serializer};
+    use crate::header::tests::{test_deserializer, test_id_version_roundtrip, test_serializer};
 
     #[test]
     fn test_standard_serializer() {
@@ -89,4 +132,34 @@ mod tests {
     fn test_standard_deserializer() {
         test_deserializer::<StandardHeaderParser>()
     }
+
+    #[test]
+    fn test_standard_parse_strict() {
+        for (bits, known) in [(0x0000, true), (0x007F, true), (0x0080, false)] {
+            let header = Header::new(1, 1, MessageFlags::from(bits), 0, 1);
+            let bytes = header.to_bytes::<StandardHeaderParser>();
+
+            match StandardHeaderParser::parse_strict(&bytes) {
+                Ok(parsed) => {
+                    assert!(known, "flags {bits:#06x} should be rejected");
+                    assert_eq!(parsed, Some(header));
+                }
+                Err(ProtocolError::UnknownFlags { bits: got }) => {
+                    assert!(!known, "flags {bits:#06x} should be accepted");
+                    assert_eq!(got, bits);
+                }
+                Err(err) => panic!("unexpected error: {err}"),
+            }
+        }
+
+        assert!(matches!(
+            StandardHeaderParser::parse_strict(&[0; HEADER_SIZE - 1]),
+            Ok(None)
+        ));
+    }
+
+    #[test]
+    fn test_standard_id_version_roundtrip() {
+        test_id_version_roundtrip::<StandardHeaderParser, StandardHeaderParser>()
+    }
 }
@@ -0,0 +1,197 @@
+//! Header layout with a variable-length sequence number.
+//!
+//! The first [`VARINT_PREFIX_SIZE`] bytes are laid out exactly like the standard header (id and
+//! version, flags, payload length). The sequence number that follows is encoded as a prefix
+//! varint of 1 to 9 bytes, where the first byte alone determines the total length:
+//!
+//! | first byte  | length | value                                      |
+//! |-------------|--------|--------------------------------------------|
+//! | `0..=240`   | 1      | the byte itself                            |
+//! | `241..=248` | 2      | `240 + 256 * (b0 - 241) + b1`              |
+//! | `249`       | 3      | `2288 + 256 * b1 + b2`                     |
+//! | `250..=255` | 4..=9  | the next `b0 - 247` bytes as big endian    |
+//!
+//! Both peers have to agree on this layout up front, see `Transport::with_varint_sequence`.
+
+use crate::constants::{FLAGS_OFFSET, PAYLOAD_OFFSET, SEQ_OFFSET};
+use crate::header::Header;
+use crate::message_flags::MessageFlags;
+use crate::protocol_params::VERSION_BITS;
+
+/// Size of the fixed part of the header preceding the varint sequence number.
+pub const VARINT_PREFIX_SIZE: usize = SEQ_OFFSET;
+/// Largest encoded size of a sequence number.
+pub const MAX_VARINT_SIZE: usize = 9;
+/// Largest encoded size of a whole varint header.
+pub const MAX_VARINT_HEADER_SIZE: usize = VARINT_PREFIX_SIZE + MAX_VARINT_SIZE;
+
+pub struct VarintHeaderParser;
+
+impl VarintHeaderParser {
+    /// Serializes `header` into `buf`, returning the number of bytes used.
+    pub fn serialize(header: &Header, buf: &mut [u8; MAX_VARINT_HEADER_SIZE]) -> usize {
+        buf[0] = ((header.id() & Header::ID_MASK) << VERSION_BITS)
+            | (header.version() & Header::VERSION_MASK);
+        buf[FLAGS_OFFSET..PAYLOAD_OFFSET].copy_from_slice(&header.flags().to_be_bytes());
+        buf[PAYLOAD_OFFSET..SEQ_OFFSET].copy_from_slice(&header.payload_len().to_be_bytes());
+
+        let seq_len = encode_varint(
+            header.sequence_number(),
+            (&mut buf[VARINT_PREFIX_SIZE..]).try_into().unwrap(),
+        );
+
+        VARINT_PREFIX_SIZE + seq_len
+    }
+
+    /// Parses a varint header from the start of `bytes`, returning it along with the number of
+    /// bytes it occupied. Returns `None` if `bytes` is too short.
+    pub fn parse(bytes: &[u8]) -> Option<(Header, usize)> {
+        if bytes.len() <= VARINT_PREFIX_SIZE {
+            return None;
+        }
+
+        let first_byte = bytes[0];
+        let id = first_byte >> VERSION_BITS;
+        let version = first_byte & Header::VERSION_MASK;
+        let flags = u16::from_be_bytes(bytes[FLAGS_OFFSET..PAYLOAD_OFFSET].try_into().unwrap());
+        let payload_len = u32::from_be_bytes(bytes[PAYLOAD_OFFSET..SEQ_OFFSET].try_into().unwrap());
+
+        let (sequence_number, seq_len) = decode_varint(&bytes[VARINT_PREFIX_SIZE..])?;
+
+        Some((
+            Header::new(
+                id,
+                version,
+                MessageFlags::from(flags),
+                payload_len,
+                sequence_number,
+            ),
+            VARINT_PREFIX_SIZE + seq_len,
+        ))
+    }
+}
+
+/// Total encoded length of a varint, determined by its first byte.
+#[inline]
+pub fn varint_len(first_byte: u8) -> usize {
+    match first_byte {
+        0..=240 => 1,
+        241..=248 => 2,
+        249 => 3,
+        _ => first_byte as usize - 246,
+    }
+}
+
+/// Encodes `value` into `out`, returning the number of bytes written.
+pub fn encode_varint(value: u64, out: &mut [u8; MAX_VARINT_SIZE]) -> usize {
+    match value {
+        0..=240 => {
+            out[0] = value as u8;
+            1
+        }
+        241..=2287 => {
+            let value = value - 240;
+            out[0] = (value / 256 + 241) as u8;
+            out[1] = (value % 256) as u8;
+            2
+        }
+        2288..=67823 => {
+            let value = value - 2288;
+            out[0] = 249;
+            out[1] = (value / 256) as u8;
+            out[2] = (value % 256) as u8;
+            3
+        }
+        _ => {
+            let bytes = value.to_be_bytes();
+            let significant = 8 - (value.leading_zeros() as usize / 8);
+            let significant = significant.max(3);
+
+            out[0] = (247 + significant) as u8;
+            out[1..=significant].copy_from_slice(&bytes[8 - significant..]);
+
+            significant + 1
+        }
+    }
+}
+
+/// Decodes a varint from the start of `bytes`, returning the value and its encoded length.
+pub fn decode_varint(bytes: &[u8]) -> Option<(u64, usize)> {
+    let first_byte = *bytes.first()?;
+    let len = varint_len(first_byte);
+
+    if bytes.len() < len {
+        return None;
+    }
+
+    let value = match first_byte {
+        0..=240 => first_byte as u64,
+        241..=248 => 240 + 256 * (first_byte as u64 - 241) + bytes[1] as u64,
+        249 => 2288 + 256 * bytes[1] as u64 + bytes[2] as u64,
+        _ => {
+            let mut buf = [0u8; 8];
+            buf[8 - (len - 1)..].copy_from_slice(&bytes[1..len]);
+            u64::from_be_bytes(buf)
+        }
+    };
+
+    Some((value, len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(sequence_number: u64) -> usize {
+        let header = Header::new(9, 2, MessageFlags::HAS_PAYLOAD, 0x1234, sequence_number);
+
+        let mut buf = [0u8; MAX_VARINT_HEADER_SIZE];
+        let len = VarintHeaderParser::serialize(&header, &mut buf);
+
+        assert_eq!(VarintHeaderParser::parse(&buf[..len]), Some((header, len)));
+        assert_eq!(VarintHeaderParser::parse(&buf[..len - 1]), None);
+
+        len
+    }
+
+    #[test]
+    fn test_small_sequence_number() {
+        for seq in [0, 1, 42, 240] {
+            assert_eq!(roundtrip(seq), VARINT_PREFIX_SIZE + 1);
+        }
+    }
+
+    #[test]
+    fn test_large_sequence_number() {
+        for seq in [1 << 56, u64::MAX - 1, u64::MAX] {
+            assert_eq!(roundtrip(seq), VARINT_PREFIX_SIZE + MAX_VARINT_SIZE);
+        }
+    }
+
+    #[test]
+    fn test_varint_boundaries() {
+        let cases = [
+            (240, 1),
+            (241, 2),
+            (2287, 2),
+            (2288, 3),
+            (67823, 3),
+            (67824, 4),
+            (0xFF_FFFF, 4),
+            (0x100_0000, 5),
+            (u32::MAX as u64, 5),
+            (u32::MAX as u64 + 1, 6),
+            (0xFF_FFFF_FFFF_FFFF, 8),
+            (0x100_0000_0000_0000, 9),
+        ];
+
+        for (value, expected_len) in cases {
+            let mut buf = [0u8; MAX_VARINT_SIZE];
+            let len = encode_varint(value, &mut buf);
+
+            assert_eq!(len, expected_len, "encoded length of {value}");
+            assert_eq!(varint_len(buf[0]), len);
+            assert_eq!(decode_varint(&buf[..len]), Some((value, len)));
+        }
+    }
+}
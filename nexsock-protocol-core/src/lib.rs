@@ -1,9 +1,31 @@
+#![cfg_attr(not(feature = "std"), no_std)]
 #![cfg_attr(feature = "simd", feature(portable_simd))]
 
+//! Without the default `std` feature only the synchronous parts are built: [`header`],
+//! [`message_flags`], [`frame`] and the traits they need, on top of `core` and `alloc`.
+
+extern crate alloc;
+
+pub mod codec;
 pub mod constants;
 pub mod error;
 pub mod frame;
 pub mod header;
 pub mod message_flags;
-mod traits;
+#[cfg(feature = "std")]
+pub mod pool;
+pub mod protocol_params;
+#[cfg(feature = "std")]
+pub mod recording;
+#[cfg(feature = "std")]
+pub mod registry;
+pub mod schema;
+pub mod traits;
+#[cfg(feature = "std")]
 pub mod transport;
+
+/// Paths used by the code `#[derive(MessageBody)]` expands to. Not public API.
+#[doc(hidden)]
+pub mod __private {
+    pub use bincode;
+}
@@ -1,27 +1,106 @@
-use std::ops::Deref;
+use core::ops::Deref;
 
-#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Default, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct MessageFlags(u16);
 
+/// Defines the flag constants along with [`MessageFlags::ALL`] and the table behind
+/// [`MessageFlags::defined`], so the three can't drift apart.
+macro_rules! message_flags {
+    ($($name:ident = $bits:expr),* $(,)?) => {
+        impl MessageFlags {
+            $(pub const $name: MessageFlags = MessageFlags($bits);)*
+
+            /// Every flag defined by this version of the protocol.
+            pub const ALL: MessageFlags = MessageFlags(0 $(| $bits)*);
+
+            const DEFINED: &'static [(&'static str, u16)] = &[$((stringify!($name), $bits)),*];
+        }
+    };
+}
+
+message_flags! {
+    COMPRESSED = 1 << 0,
+    ENCRYPTED = 1 << 1,
+    REQUIRES_ACK = 1 << 2,
+    HAS_PAYLOAD = 1 << 3,
+    HAS_CHECKSUM = 1 << 4,
+    FRAGMENTED = 1 << 5,
+    LAST_FRAGMENT = 1 << 6,
+}
+
 impl MessageFlags {
     pub const NONE: MessageFlags = MessageFlags(0);
-    pub const COMPRESSED: MessageFlags = MessageFlags(1 << 0);
-    pub const ENCRYPTED: MessageFlags = MessageFlags(1 << 1);
-    pub const REQUIRES_ACK: MessageFlags = MessageFlags(1 << 2);
-    pub const HAS_PAYLOAD: MessageFlags = MessageFlags(1 << 3);
+
+    /// Bits covered by a defined flag; anything outside of it is unknown.
+    pub const KNOWN_MASK: u16 = Self::ALL.0;
+
+    /// Name and bit value of every defined flag, in bit order.
+    #[inline]
+    pub fn defined() -> &'static [(&'static str, u16)] {
+        Self::DEFINED
+    }
+
+    /// Like `MessageFlags::from`, but returns `None` if `bits` has any bit set that doesn't belong
+    /// to a defined flag.
+    #[inline]
+    pub fn from_bits_checked(bits: u16) -> Option<MessageFlags> {
+        if bits & !Self::KNOWN_MASK == 0 {
+            Some(MessageFlags(bits))
+        } else {
+            None
+        }
+    }
 
     #[inline]
     pub fn contains(self, other: MessageFlags) -> bool {
         (self.0 & other.0) == other.0
     }
 
+    /// Whether `self` and `other` share at least one set bit.
+    #[inline]
+    pub fn intersects(self, other: MessageFlags) -> bool {
+        (self.0 & other.0) != 0
+    }
+
     #[inline]
     pub fn is_empty(self) -> bool {
         self.0 == 0
     }
+
+    #[inline]
+    pub fn insert(&mut self, other: MessageFlags) {
+        self.0 |= other.0;
+    }
+
+    #[inline]
+    pub fn remove(&mut self, other: MessageFlags) {
+        self.0 &= !other.0;
+    }
+
+    #[inline]
+    pub fn toggle(&mut self, other: MessageFlags) {
+        self.0 ^= other.0;
+    }
+
+    /// Every set bit as a flag of its own, in bit order. Unknown bits are included as they are.
+    pub fn iter(self) -> impl Iterator<Item = MessageFlags> {
+        (0..u16::BITS)
+            .map(|bit| 1 << bit)
+            .filter(move |bit| self.0 & bit != 0)
+            .map(MessageFlags)
+    }
+
+    /// Names of the defined flags that are set, in bit order. Unknown bits have no name and are
+    /// skipped.
+    pub fn names(self) -> impl Iterator<Item = &'static str> {
+        Self::defined()
+            .iter()
+            .filter(move |&&(_, bits)| self.0 & bits != 0)
+            .map(|&(name, _)| name)
+    }
 }
 
-impl std::ops::BitOr for MessageFlags {
+impl core::ops::BitOr for MessageFlags {
     type Output = Self;
 
     fn bitor(self, rhs: Self) -> Self::Output {
@@ -29,7 +108,7 @@ impl std::ops::BitOr for MessageFlags {
     }
 }
 
-impl std::ops::BitAnd for MessageFlags {
+impl core::ops::BitAnd for MessageFlags {
     type Output = Self;
 
     fn bitand(self, rhs: Self) -> Self::Output {
@@ -37,6 +116,64 @@ impl std::ops::BitAnd for MessageFlags {
     }
 }
 
+impl core::ops::BitXor for MessageFlags {
+    type Output = Self;
+
+    fn bitxor(self, rhs: Self) -> Self::Output {
+        MessageFlags(self.0 ^ rhs.0)
+    }
+}
+
+impl core::ops::BitOrAssign for MessageFlags {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl core::ops::BitAndAssign for MessageFlags {
+    fn bitand_assign(&mut self, rhs: Self) {
+        self.0 &= rhs.0;
+    }
+}
+
+impl core::ops::Not for MessageFlags {
+    type Output = Self;
+
+    fn not(self) -> Self::Output {
+        MessageFlags(!self.0)
+    }
+}
+
+/// Renders the set flags by name, e.g. `COMPRESSED | HAS_PAYLOAD`, or `NONE` when empty. Unknown
+/// bits are appended as hex so nothing is lost.
+impl core::fmt::Display for MessageFlags {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if self.is_empty() {
+            return f.write_str("NONE");
+        }
+
+        let mut separator = "";
+
+        for name in self.names() {
+            write!(f, "{separator}{name}")?;
+            separator = " | ";
+        }
+
+        let unknown = self.0 & !Self::KNOWN_MASK;
+        if unknown != 0 {
+            write!(f, "{separator}{unknown:#06x}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl core::fmt::Debug for MessageFlags {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "MessageFlags({self}, {:#06x})", self.0)
+    }
+}
+
 impl AsRef<u16> for MessageFlags {
     fn as_ref(&self) -> &u16 {
         &self.0
@@ -57,9 +194,87 @@ impl From<u16> for MessageFlags {
     }
 }
 
+/// With the `serde` feature, flags serialize as an array of flag names, e.g.
+/// `["COMPRESSED", "HAS_PAYLOAD"]`. Unknown bits have no name, so serializing them fails, as does
+/// deserializing a name this version doesn't define.
+#[cfg(feature = "serde")]
+impl serde::Serialize for MessageFlags {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let unknown = self.0 & !Self::KNOWN_MASK;
+        if unknown != 0 {
+            return Err(serde::ser::Error::custom(format_args!(
+                "unknown flag bits {unknown:#06x}"
+            )));
+        }
+
+        serializer.collect_seq(self.names())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for MessageFlags {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_seq(FlagsVisitor)
+    }
+}
+
+#[cfg(feature = "serde")]
+struct FlagsVisitor;
+
+#[cfg(feature = "serde")]
+impl<'de> serde::de::Visitor<'de> for FlagsVisitor {
+    type Value = MessageFlags;
+
+    fn expecting(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("an array of message flag names")
+    }
+
+    fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut flags = MessageFlags::NONE;
+        while let Some(FlagName(bits)) = seq.next_element()? {
+            flags.0 |= bits;
+        }
+
+        Ok(flags)
+    }
+}
+
+/// A single flag name, resolved to its bit value.
+#[cfg(feature = "serde")]
+struct FlagName(u16);
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for FlagName {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_str(FlagNameVisitor)
+    }
+}
+
+#[cfg(feature = "serde")]
+struct FlagNameVisitor;
+
+#[cfg(feature = "serde")]
+impl serde::de::Visitor<'_> for FlagNameVisitor {
+    type Value = FlagName;
+
+    fn expecting(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("a message flag name")
+    }
+
+    fn visit_str<E: serde::de::Error>(self, name: &str) -> Result<Self::Value, E> {
+        MessageFlags::defined()
+            .iter()
+            .find(|&&(defined, _)| defined == name)
+            .map(|&(_, bits)| FlagName(bits))
+            .ok_or_else(|| E::custom(format_args!("unknown message flag `{name}`")))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use alloc::format;
+    use alloc::string::ToString;
 
     #[test]
     fn test_flags() {
@@ -70,4 +285,132 @@ mod tests {
 
         assert!(!flag.contains(MessageFlags::HAS_PAYLOAD));
     }
+
+    #[test]
+    fn test_defined() {
+        let constants = [
+            ("COMPRESSED", MessageFlags::COMPRESSED),
+            ("ENCRYPTED", MessageFlags::ENCRYPTED),
+            ("REQUIRES_ACK", MessageFlags::REQUIRES_ACK),
+            ("HAS_PAYLOAD", MessageFlags::HAS_PAYLOAD),
+            ("HAS_CHECKSUM", MessageFlags::HAS_CHECKSUM),
+            ("FRAGMENTED", MessageFlags::FRAGMENTED),
+            ("LAST_FRAGMENT", MessageFlags::LAST_FRAGMENT),
+        ];
+
+        assert_eq!(MessageFlags::defined().len(), constants.len());
+
+        for (name, flag) in constants {
+            let matches = MessageFlags::defined()
+                .iter()
+                .filter(|&&(defined_name, bits)| defined_name == name && bits == *flag)
+                .count();
+
+            assert_eq!(matches, 1, "{name} should be defined exactly once");
+        }
+
+        let all = MessageFlags::defined()
+            .iter()
+            .fold(0, |acc, &(_, bits)| acc | bits);
+        assert_eq!(all, *MessageFlags::ALL);
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            (MessageFlags::COMPRESSED | MessageFlags::HAS_PAYLOAD).to_string(),
+            "COMPRESSED | HAS_PAYLOAD"
+        );
+        assert_eq!(MessageFlags::from(0x0080).to_string(), "0x0080");
+        assert_eq!(MessageFlags::from(0x0082).to_string(), "ENCRYPTED | 0x0080");
+        assert_eq!(MessageFlags::NONE.to_string(), "NONE");
+
+        assert_eq!(
+            format!("{:?}", MessageFlags::COMPRESSED | MessageFlags::HAS_PAYLOAD),
+            "MessageFlags(COMPRESSED | HAS_PAYLOAD, 0x0009)"
+        );
+    }
+
+    #[test]
+    fn test_from_bits_checked() {
+        assert_eq!(
+            MessageFlags::from_bits_checked(0x0000),
+            Some(MessageFlags::NONE)
+        );
+        assert_eq!(
+            MessageFlags::from_bits_checked(0x000F),
+            Some(MessageFlags::from(0x000F))
+        );
+        assert_eq!(
+            MessageFlags::from_bits_checked(0x007F),
+            Some(MessageFlags::ALL)
+        );
+        assert_eq!(MessageFlags::from_bits_checked(0x0080), None);
+    }
+
+    #[test]
+    fn test_flag_manipulation() {
+        let mut flags = MessageFlags::NONE;
+
+        flags.insert(MessageFlags::COMPRESSED);
+        assert!(flags.contains(MessageFlags::COMPRESSED));
+
+        flags.remove(MessageFlags::COMPRESSED);
+        assert!(flags.is_empty());
+
+        flags.toggle(MessageFlags::ENCRYPTED);
+        assert_eq!(flags, MessageFlags::ENCRYPTED);
+        flags.toggle(MessageFlags::ENCRYPTED);
+        assert!(flags.is_empty());
+
+        flags |= MessageFlags::REQUIRES_ACK | MessageFlags::HAS_PAYLOAD;
+        flags &= !MessageFlags::REQUIRES_ACK;
+        assert_eq!(flags, MessageFlags::HAS_PAYLOAD);
+        assert_eq!(
+            flags ^ MessageFlags::HAS_PAYLOAD ^ MessageFlags::COMPRESSED,
+            MessageFlags::COMPRESSED
+        );
+
+        let partial = MessageFlags::COMPRESSED | MessageFlags::ENCRYPTED;
+        let other = MessageFlags::ENCRYPTED | MessageFlags::HAS_PAYLOAD;
+        assert!(partial.intersects(other));
+        assert!(!partial.contains(other));
+        assert!(!partial.intersects(MessageFlags::REQUIRES_ACK));
+    }
+
+    #[test]
+    fn test_iter() {
+        let flags = MessageFlags::REQUIRES_ACK | MessageFlags::COMPRESSED;
+
+        assert!(
+            flags
+                .iter()
+                .eq([MessageFlags::COMPRESSED, MessageFlags::REQUIRES_ACK])
+        );
+        assert!(flags.names().eq(["COMPRESSED", "REQUIRES_ACK"]));
+
+        let unknown = MessageFlags::HAS_PAYLOAD | MessageFlags::from(0x8000);
+        assert!(
+            unknown
+                .iter()
+                .eq([MessageFlags::HAS_PAYLOAD, MessageFlags::from(0x8000)])
+        );
+        assert!(unknown.names().eq(["HAS_PAYLOAD"]));
+
+        assert_eq!(MessageFlags::NONE.iter().count(), 0);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_names() {
+        let flags = MessageFlags::COMPRESSED | MessageFlags::HAS_PAYLOAD;
+        let json = serde_json::to_string(&flags).unwrap();
+
+        assert_eq!(json, r#"["COMPRESSED","HAS_PAYLOAD"]"#);
+        assert_eq!(serde_json::from_str::<MessageFlags>(&json).unwrap(), flags);
+        assert_eq!(serde_json::to_string(&MessageFlags::NONE).unwrap(), "[]");
+
+        assert!(serde_json::from_str::<MessageFlags>(r#"["COMPRESSED","BOGUS"]"#).is_err());
+        assert!(serde_json::to_string(&MessageFlags::from(0x0080)).is_err());
+    }
 }
@@ -0,0 +1,110 @@
+use bytes::BytesMut;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+
+/// A shared pool of reusable payload buffers.
+///
+/// Buffers are handed out as [`PooledBuffer`] guards which put the buffer back into the pool when
+/// dropped, so a busy reader doesn't need a fresh allocation per message. At most `max_buffers`
+/// idle buffers are retained; anything beyond that is simply freed.
+#[derive(Debug, Clone)]
+pub struct BufferPool {
+    buffers: Arc<Mutex<Vec<BytesMut>>>,
+    max_buffers: usize,
+}
+
+impl BufferPool {
+    pub fn new(max_buffers: usize) -> Self {
+        Self {
+            buffers: Arc::new(Mutex::new(Vec::with_capacity(max_buffers))),
+            max_buffers,
+        }
+    }
+
+    /// Takes a buffer from the pool (or allocates one if the pool is empty) and resizes it to
+    /// `len` zeroed bytes.
+    pub fn acquire(&self, len: usize) -> PooledBuffer {
+        let mut buffer = self
+            .buffers
+            .lock()
+            .expect("buffer pool poisoned")
+            .pop()
+            .unwrap_or_default();
+
+        buffer.clear();
+        buffer.resize(len, 0);
+
+        PooledBuffer {
+            buffer: Some(buffer),
+            pool: Some(self.clone()),
+        }
+    }
+
+    /// Number of idle buffers currently held by the pool.
+    pub fn available(&self) -> usize {
+        self.buffers.lock().expect("buffer pool poisoned").len()
+    }
+
+    fn release(&self, buffer: BytesMut) {
+        let mut buffers = self.buffers.lock().expect("buffer pool poisoned");
+
+        if buffers.len() < self.max_buffers {
+            buffers.push(buffer);
+        }
+    }
+}
+
+impl Default for BufferPool {
+    fn default() -> Self {
+        Self::new(64)
+    }
+}
+
+/// A payload buffer borrowed from a [`BufferPool`], returned to it on drop.
+#[derive(Debug)]
+pub struct PooledBuffer {
+    buffer: Option<BytesMut>,
+    pool: Option<BufferPool>,
+}
+
+impl PooledBuffer {
+    /// Wraps a buffer that doesn't belong to any pool.
+    pub(crate) fn unpooled(buffer: BytesMut) -> Self {
+        Self {
+            buffer: Some(buffer),
+            pool: None,
+        }
+    }
+
+    /// Detaches the buffer from the pool, taking ownership of it.
+    pub fn into_inner(mut self) -> BytesMut {
+        self.pool = None;
+        self.buffer.take().unwrap_or_default()
+    }
+}
+
+impl Deref for PooledBuffer {
+    type Target = BytesMut;
+
+    fn deref(&self) -> &Self::Target {
+        self.buffer
+            .as_ref()
+            .expect("pooled buffer already released")
+    }
+}
+
+impl DerefMut for PooledBuffer {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.buffer
+            .as_mut()
+            .expect("pooled buffer already released")
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        if let (Some(pool), Some(buffer)) = (self.pool.take(), self.buffer.take()) {
+            pool.release(buffer);
+        }
+    }
+}
@@ -0,0 +1,64 @@
+//! Wire constants of the protocol, kept in one place so a fork can change them without hunting
+//! down literals.
+//!
+//! The header parsers and [`Header`](crate::header::Header)'s range checks are built from the
+//! constants here. A transport can additionally be switched to a different magic and id/version
+//! split at runtime with [`Transport::with_params`](crate::transport::Transport::with_params),
+//! which is enough for talking to a fork that only narrows the id in favour of the version.
+
+/// Bytes every frame starts with.
+pub const MAGIC: [u8; 4] = *b"NEX\0";
+/// Width of the message id in the first header byte.
+pub const ID_BITS: u32 = 6;
+/// Width of the version in the first header byte, below the id.
+pub const VERSION_BITS: u32 = 2;
+
+const _: () = assert!(
+    ID_BITS + VERSION_BITS == 8,
+    "id and version must fill one byte"
+);
+
+/// A set of wire constants, see the [module docs](self). `ID_BITS` and `VERSION_BITS` have to
+/// add up to 8.
+pub trait ProtocolParams {
+    const MAGIC: [u8; 4];
+    const ID_BITS: u32;
+    const VERSION_BITS: u32;
+
+    /// Packs `id` and `version` into the first header byte, dropping bits that don't fit.
+    #[inline]
+    fn pack(id: u8, version: u8) -> u8 {
+        let version_mask = (1u8 << Self::VERSION_BITS) - 1;
+
+        (id << Self::VERSION_BITS) | (version & version_mask)
+    }
+
+    /// Splits the first header byte into id and version.
+    #[inline]
+    fn unpack(byte: u8) -> (u8, u8) {
+        let version_mask = (1u8 << Self::VERSION_BITS) - 1;
+
+        (byte >> Self::VERSION_BITS, byte & version_mask)
+    }
+}
+
+/// The constants this crate is built with.
+pub struct DefaultParams;
+
+impl ProtocolParams for DefaultParams {
+    const MAGIC: [u8; 4] = MAGIC;
+    const ID_BITS: u32 = ID_BITS;
+    const VERSION_BITS: u32 = VERSION_BITS;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_pack() {
+        assert_eq!(DefaultParams::pack(0x3F, 0x03), 0xFF);
+        assert_eq!(DefaultParams::pack(5, 1), (5 << 2) | 1);
+        assert_eq!(DefaultParams::unpack((5 << 2) | 1), (5, 1));
+    }
+}
@@ -0,0 +1,188 @@
+//! Recording of transport sessions, so they can be replayed later to reproduce a bug.
+
+use crate::constants::HEADER_SIZE;
+use crate::error::ProtocolResult;
+use crate::frame::Frame;
+use crate::header::Header;
+use crate::header::standard::StandardHeaderParser;
+use crate::traits::MessageBody;
+use crate::transport::Transport;
+use bytes::Bytes;
+use std::io;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// Which way a recorded frame travelled, from the point of view of the recording transport.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+/// Ordered list of every frame a [`RecordingTransport`] read or wrote.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FrameLog {
+    frames: Vec<(Direction, Header, Bytes)>,
+}
+
+impl FrameLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, direction: Direction, header: Header, payload: Bytes) {
+        self.frames.push((direction, header, payload));
+    }
+
+    /// All recorded frames, in the order they were read or written.
+    pub fn frames(&self) -> &[(Direction, Header, Bytes)] {
+        &self.frames
+    }
+
+    /// The frames that were written, in order.
+    pub fn outbound(&self) -> impl Iterator<Item = (&Header, &Bytes)> {
+        self.frames
+            .iter()
+            .filter(|(direction, _, _)| *direction == Direction::Outbound)
+            .map(|(_, header, payload)| (header, payload))
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+}
+
+/// Wraps a [`Transport`], recording every frame that passes through it into a [`FrameLog`].
+pub struct RecordingTransport<R: AsyncRead + Unpin, W: AsyncWrite + Unpin> {
+    inner: Transport<R, W>,
+    log: FrameLog,
+}
+
+impl<R: AsyncRead + Unpin, W: AsyncWrite + Unpin> RecordingTransport<R, W> {
+    pub fn new(inner: Transport<R, W>) -> Self {
+        Self {
+            inner,
+            log: FrameLog::new(),
+        }
+    }
+
+    pub fn log(&self) -> &FrameLog {
+        &self.log
+    }
+
+    /// Stops recording, handing back the transport and everything recorded so far.
+    pub fn into_parts(self) -> (Transport<R, W>, FrameLog) {
+        (self.inner, self.log)
+    }
+
+    /// See [`Transport::read_raw`].
+    pub async fn read_raw(&mut self) -> ProtocolResult<(Header, Bytes)> {
+        let (header, payload) = self.inner.read_raw().await?;
+        self.log.push(Direction::Inbound, header, payload.clone());
+
+        Ok((header, payload))
+    }
+
+    /// See [`Transport::read_message`].
+    pub async fn read_message<T: MessageBody + 'static>(&mut self) -> ProtocolResult<T> {
+        let (header, payload) = self.read_raw().await?;
+
+        self.inner.decode_message(&header, &payload)
+    }
+
+    /// See [`Transport::write_message`].
+    pub async fn write_message<T: MessageBody>(
+        &mut self,
+        message: Frame<{ HEADER_SIZE }, T>,
+    ) -> ProtocolResult<Header> {
+        let (header, payload) = self.inner.encode_message(&message)?;
+        self.inner.write_frame(&header, &payload).await?;
+        self.log.push(Direction::Outbound, header, payload.into());
+
+        Ok(header)
+    }
+
+    /// See [`Transport::write_raw`].
+    pub async fn write_raw(&mut self, frame: &Frame<{ HEADER_SIZE }, Bytes>) -> ProtocolResult<()> {
+        let header = Header::parse::<StandardHeaderParser>(&frame.header()).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "Failed to parse frame header")
+        })?;
+
+        self.inner.write_raw(frame).await?;
+        self.log
+            .push(Direction::Outbound, header, frame.body().clone());
+
+        Ok(())
+    }
+}
+
+/// Re-sends every outbound frame in `log` through `into`, in the order they were recorded.
+pub async fn replay<R: AsyncRead + Unpin, W: AsyncWrite + Unpin>(
+    log: &FrameLog,
+    into: &mut Transport<R, W>,
+) -> ProtocolResult<()> {
+    for (header, payload) in log.outbound() {
+        into.write_frame(header, payload).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message_flags::MessageFlags;
+    use crate::transport::tests::{MockReader, MockWriter, TestMessage, frame_bytes};
+
+    #[tokio::test]
+    async fn test_record_and_replay() {
+        let reply = Header::new(2, 1, MessageFlags::HAS_PAYLOAD, 4, 1);
+        let reader = MockReader::new(frame_bytes(reply, b"pong"));
+
+        let mut transport = RecordingTransport::new(Transport::new(reader, MockWriter::new()));
+
+        let header = Header::new(1, 1, MessageFlags::NONE, 0, 1);
+        transport
+            .write_message(
+                TestMessage {
+                    field1: 1,
+                    field2: "ping".to_string(),
+                }
+                .to_frame(header.to_bytes::<StandardHeaderParser>()),
+            )
+            .await
+            .unwrap();
+
+        let (read_header, payload) = transport.read_raw().await.unwrap();
+        assert_eq!(read_header, reply);
+        assert_eq!(&payload[..], b"pong");
+
+        let raw = Header::new(3, 1, MessageFlags::HAS_PAYLOAD, 3, 2);
+        transport
+            .write_raw(&Frame::from_encoded::<StandardHeaderParser>(
+                raw,
+                Bytes::from_static(b"raw"),
+            ))
+            .await
+            .unwrap();
+
+        let (original, log) = transport.into_parts();
+        let directions: Vec<_> = log.frames().iter().map(|(dir, _, _)| *dir).collect();
+        assert_eq!(
+            directions,
+            [Direction::Outbound, Direction::Inbound, Direction::Outbound]
+        );
+        assert_eq!(log.outbound().count(), 2);
+
+        let mut replayed = Transport::new(MockReader::new(Vec::new()), MockWriter::new());
+        replay(&log, &mut replayed).await.unwrap();
+
+        assert_eq!(
+            replayed.writer().written_data(),
+            original.writer().written_data()
+        );
+    }
+}
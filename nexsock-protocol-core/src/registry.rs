@@ -0,0 +1,218 @@
+use crate::error::{ProtocolError, ProtocolResult};
+use crate::traits::MessageBody;
+use std::any::{Any, TypeId, type_name};
+use std::collections::HashMap;
+
+/// A message body decoded by [`MessageRegistry::decode`], to be downcast to the registered type.
+pub type DynMessage = Box<dyn Any + Send>;
+
+#[derive(Debug, Clone, Copy)]
+struct Registration {
+    type_id: TypeId,
+    type_name: &'static str,
+    fixed_len: Option<u32>,
+    decode: fn(&[u8]) -> ProtocolResult<(DynMessage, usize)>,
+}
+
+/// Decodes a `T`, returning it along with the number of bytes it took.
+fn decode_boxed<T: MessageBody + Send + 'static>(
+    payload: &[u8],
+) -> ProtocolResult<(DynMessage, usize)> {
+    let config = bincode::config::standard().with_big_endian();
+    let (body, len): (T, _) = bincode::decode_from_slice(payload, config)?;
+
+    Ok((Box::new(body), len))
+}
+
+/// Maps message ids to the body type they're expected to carry.
+///
+/// Handing a registry to [`Transport::with_type_registry`](crate::transport::Transport::with_type_registry)
+/// makes `read_message::<T>()` fail with [`ProtocolError::TypeMismatch`] when `T` isn't the type
+/// registered for the frame's id, instead of decoding garbage or failing with an unrelated decode
+/// error. Ids without a registration are not checked.
+///
+/// Types registered with [`MessageRegistry::register_fixed`] always encode to the same number of
+/// bytes, so their frames may leave `payload_len` at zero and have the reader infer it. Headers
+/// returned by reads carry the inferred length.
+///
+/// The registry can also decode payloads by id without knowing the type up front, see
+/// [`MessageRegistry::decode`] and [`Transport::read_registered`](crate::transport::Transport::read_registered),
+/// which is what a server dispatching on the message id needs.
+#[derive(Debug, Clone, Default)]
+pub struct MessageRegistry {
+    types: HashMap<u8, Registration>,
+}
+
+impl MessageRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `T` as the body type of messages with `id`, replacing any earlier registration.
+    pub fn register<T: MessageBody + Send + 'static>(&mut self, id: u8) -> &mut Self {
+        self.insert::<T>(id, None)
+    }
+
+    /// Registers `T` as the body type of messages with `id`, like [`MessageRegistry::register`],
+    /// and declares that it always encodes to exactly `len` bytes.
+    ///
+    /// Frames with this id that set `HAS_PAYLOAD` but carry a `payload_len` of zero are read as
+    /// having a `len` byte payload.
+    ///
+    /// bincode encodes integers with a variable length, so few types are actually fixed-size;
+    /// an array of bytes is. A wrong `len` makes the reader lose track of where frames start, so
+    /// bodies with this id are checked to decode from exactly `len` bytes, failing with
+    /// [`ProtocolError::FixedLenMismatch`] otherwise.
+    pub fn register_fixed<T: MessageBody + Send + 'static>(
+        &mut self,
+        id: u8,
+        len: u32,
+    ) -> &mut Self {
+        self.insert::<T>(id, Some(len))
+    }
+
+    /// Encoded length of the fixed-size type registered for `id`, if any.
+    pub fn fixed_len(&self, id: u8) -> Option<u32> {
+        self.types
+            .get(&id)
+            .and_then(|registration| registration.fixed_len)
+    }
+
+    fn insert<T: MessageBody + Send + 'static>(
+        &mut self,
+        id: u8,
+        fixed_len: Option<u32>,
+    ) -> &mut Self {
+        self.types.insert(
+            id,
+            Registration {
+                type_id: TypeId::of::<T>(),
+                type_name: type_name::<T>(),
+                fixed_len,
+                decode: decode_boxed::<T>,
+            },
+        );
+
+        self
+    }
+
+    /// Name of the type registered for `id`, if any.
+    pub fn expected(&self, id: u8) -> Option<&'static str> {
+        self.types
+            .get(&id)
+            .map(|registration| registration.type_name)
+    }
+
+    /// Checks that `T` is the type registered for `id`.
+    pub fn check<T: 'static>(&self, id: u8) -> ProtocolResult<()> {
+        match self.types.get(&id) {
+            Some(registration) if registration.type_id != TypeId::of::<T>() => {
+                Err(ProtocolError::TypeMismatch {
+                    id,
+                    expected: registration.type_name,
+                    got: type_name::<T>(),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Decodes `payload` with bincode as the type registered for `id`, failing with
+    /// [`ProtocolError::UnregisteredId`] if there is none.
+    ///
+    /// The result can be downcast with [`Box::downcast`] or `downcast_ref`.
+    pub fn decode(&self, id: u8, payload: &[u8]) -> ProtocolResult<DynMessage> {
+        let registration = self
+            .types
+            .get(&id)
+            .ok_or(ProtocolError::UnregisteredId { id })?;
+
+        let (body, len) = (registration.decode)(payload)?;
+        check_len(id, registration, payload, len)?;
+
+        Ok(body)
+    }
+
+    /// Checks that `payload` decodes from exactly the registered length, if `id` is registered
+    /// with [`MessageRegistry::register_fixed`].
+    pub fn check_fixed_len(&self, id: u8, payload: &[u8]) -> ProtocolResult<()> {
+        match self.types.get(&id) {
+            Some(registration) if registration.fixed_len.is_some() => {
+                let (_, len) = (registration.decode)(payload)?;
+
+                check_len(id, registration, payload, len)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Fails if a fixed-size body didn't take up exactly its registered length, either in the
+/// payload or when decoded.
+fn check_len(
+    id: u8,
+    registration: &Registration,
+    payload: &[u8],
+    decoded: usize,
+) -> ProtocolResult<()> {
+    match registration.fixed_len {
+        Some(expected) if payload.len() != expected as usize || decoded != expected as usize => {
+            Err(ProtocolError::FixedLenMismatch {
+                id,
+                expected,
+                got: decoded,
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let mut registry = MessageRegistry::new();
+        registry.register::<()>(1);
+
+        assert!(registry.check::<()>(1).is_ok());
+        assert!(registry.check::<u32>(2).is_ok());
+        assert_eq!(registry.expected(1), Some("()"));
+
+        assert_eq!(registry.fixed_len(1), None);
+
+        assert!(matches!(
+            registry.check::<u32>(1),
+            Err(ProtocolError::TypeMismatch {
+                id: 1,
+                expected: "()",
+                got: "u32"
+            })
+        ));
+    }
+
+    #[test]
+    fn test_decode() {
+        let config = bincode::config::standard().with_big_endian();
+
+        let mut registry = MessageRegistry::new();
+        registry.register::<u32>(1).register::<String>(2);
+
+        let payload = bincode::encode_to_vec(7u32, config).unwrap();
+        let body = registry.decode(1, &payload).unwrap();
+        assert_eq!(body.downcast_ref::<u32>(), Some(&7));
+
+        let payload = bincode::encode_to_vec("seven", config).unwrap();
+        let body = registry.decode(2, &payload).unwrap();
+        assert_eq!(
+            body.downcast_ref::<String>().map(String::as_str),
+            Some("seven")
+        );
+
+        assert!(matches!(
+            registry.decode(3, &payload),
+            Err(ProtocolError::UnregisteredId { id: 3 })
+        ));
+    }
+}
@@ -0,0 +1,111 @@
+//! Decoding a payload against several candidate types, for reading messages across schema
+//! changes, see [`Transport::read_any`](crate::transport::Transport::read_any).
+//!
+//! Candidates are given as a tuple, newest first, and the payload is decoded as each in turn until
+//! one of them consumes it exactly. Trailing bytes count as a failure, so an older, shorter type
+//! doesn't accidentally match a prefix of a newer message.
+
+use crate::error::{ProtocolError, ProtocolResult};
+use crate::traits::MessageBody;
+use bincode::error::DecodeError;
+use core::convert::Infallible;
+
+/// The candidate a payload was decoded as, by position in the tuple given to
+/// [`SchemaChain::decode`].
+///
+/// Unused positions are [`Infallible`], so matching on them can be skipped for shorter chains.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemaVariant<A, B = Infallible, C = Infallible, D = Infallible> {
+    First(A),
+    Second(B),
+    Third(C),
+    Fourth(D),
+}
+
+/// A tuple of up to four candidate message types, newest first.
+pub trait SchemaChain {
+    type Variant;
+
+    /// Decodes `payload` as the first candidate that consumes all of it. If none does, the error
+    /// from the last candidate is returned.
+    fn decode(payload: &[u8]) -> ProtocolResult<Self::Variant>;
+}
+
+/// Decodes `payload` as `T`, failing unless every byte is used.
+fn decode_exact<T: MessageBody>(payload: &[u8]) -> ProtocolResult<T> {
+    let config = bincode::config::standard().with_big_endian();
+    let (body, used) = bincode::decode_from_slice(payload, config)?;
+
+    if used != payload.len() {
+        return Err(ProtocolError::Decode(DecodeError::Other(
+            "trailing bytes after the message",
+        )));
+    }
+
+    Ok(body)
+}
+
+macro_rules! schema_chain {
+    ($first:ident => $first_variant:ident $(, $ty:ident => $variant:ident)*) => {
+        impl<$first: MessageBody $(, $ty: MessageBody)*> SchemaChain for ($first, $($ty,)*) {
+            type Variant = SchemaVariant<$first $(, $ty)*>;
+
+            fn decode(payload: &[u8]) -> ProtocolResult<Self::Variant> {
+                let result = decode_exact::<$first>(payload).map(SchemaVariant::$first_variant);
+                $(
+                    let result = result
+                        .or_else(|_| decode_exact::<$ty>(payload).map(SchemaVariant::$variant));
+                )*
+
+                result
+            }
+        }
+    };
+}
+
+schema_chain!(A => First);
+schema_chain!(A => First, B => Second);
+schema_chain!(A => First, B => Second, C => Third);
+schema_chain!(A => First, B => Second, C => Third, D => Fourth);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::{String, ToString};
+    use bincode::{Decode, Encode};
+
+    #[derive(Debug, PartialEq, Encode, Decode)]
+    struct V1 {
+        id: u32,
+    }
+
+    #[derive(Debug, PartialEq, Encode, Decode)]
+    struct V2 {
+        id: u32,
+        name: String,
+    }
+
+    impl MessageBody for V1 {}
+    impl MessageBody for V2 {}
+
+    #[test]
+    fn test_trailing_bytes_rejected() {
+        let config = bincode::config::standard().with_big_endian();
+        let payload = bincode::encode_to_vec(
+            V2 {
+                id: 1,
+                name: "new".to_string(),
+            },
+            config,
+        )
+        .unwrap();
+
+        // V1 decodes a prefix of the payload, which must not count as a match.
+        assert!(decode_exact::<V1>(&payload).is_err());
+        assert!(matches!(
+            <(V1, V2) as SchemaChain>::decode(&payload),
+            Ok(SchemaVariant::Second(V2 { id: 1, .. }))
+        ));
+        assert!(<(V1,) as SchemaChain>::decode(&payload).is_err());
+    }
+}
@@ -0,0 +1,21 @@
+use bytes::BytesMut;
+
+/// Allocates the payload buffers used on the read path.
+///
+/// Implement this to route payload allocations through an arena, a bump allocator or a pool. The
+/// transport resizes the returned buffer to the length it needs, so `alloc` only has to provide
+/// at least `len` bytes of capacity for the transport to avoid reallocating.
+pub trait BufferAllocator: Send + Sync {
+    fn alloc(&self, len: usize) -> BytesMut;
+}
+
+/// Allocates straight from the global allocator.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DefaultBufferAllocator;
+
+impl BufferAllocator for DefaultBufferAllocator {
+    #[inline]
+    fn alloc(&self, len: usize) -> BytesMut {
+        BytesMut::with_capacity(len)
+    }
+}
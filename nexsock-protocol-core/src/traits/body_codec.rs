@@ -0,0 +1,97 @@
+use crate::error::ProtocolResult;
+use crate::traits::MessageBody;
+use alloc::vec::Vec;
+
+/// Turns message bodies of type `T` into payload bytes and back.
+///
+/// The transport only deals in payloads, so any encoding can be spoken over the same framing by
+/// implementing this, see [`Transport::with_codec`](crate::transport::Transport::with_codec).
+/// Both peers have to use the same codec.
+pub trait BodyCodec<T> {
+    fn encode(&self, body: &T) -> ProtocolResult<Vec<u8>>;
+
+    fn decode(&self, payload: &[u8]) -> ProtocolResult<T>;
+}
+
+/// Encodes bodies with bincode, big-endian with variable-length integers. This is the codec the
+/// transport uses unless configured otherwise.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BincodeCodec;
+
+impl<T: MessageBody> BodyCodec<T> for BincodeCodec {
+    #[inline]
+    fn encode(&self, body: &T) -> ProtocolResult<Vec<u8>> {
+        let config = bincode::config::standard().with_big_endian();
+
+        Ok(bincode::encode_to_vec(body, config)?)
+    }
+
+    #[inline]
+    fn decode(&self, payload: &[u8]) -> ProtocolResult<T> {
+        let config = bincode::config::standard().with_big_endian();
+        let (body, _) = bincode::decode_from_slice(payload, config)?;
+
+        Ok(body)
+    }
+}
+
+/// Encodes bodies as JSON, e.g. to read the traffic while debugging or to talk to peers without
+/// bincode. Available with the `json` feature.
+#[cfg(feature = "json")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct JsonCodec;
+
+#[cfg(feature = "json")]
+impl<T: serde::Serialize + serde::de::DeserializeOwned> BodyCodec<T> for JsonCodec {
+    #[inline]
+    fn encode(&self, body: &T) -> ProtocolResult<Vec<u8>> {
+        Ok(serde_json::to_vec(body)?)
+    }
+
+    #[inline]
+    fn decode(&self, payload: &[u8]) -> ProtocolResult<T> {
+        Ok(serde_json::from_slice(payload)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bincode::{Decode, Encode};
+
+    #[derive(Debug, PartialEq, Encode, Decode)]
+    #[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+    struct Body {
+        id: u32,
+        name: alloc::string::String,
+    }
+
+    impl MessageBody for Body {}
+
+    fn body() -> Body {
+        Body {
+            id: 7,
+            name: "seven".into(),
+        }
+    }
+
+    #[test]
+    fn test_bincode_roundtrip() {
+        let payload = BincodeCodec.encode(&body()).unwrap();
+        let decoded: Body = BincodeCodec.decode(&payload).unwrap();
+
+        assert_eq!(decoded, body());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_json_roundtrip() {
+        let payload = JsonCodec.encode(&body()).unwrap();
+        assert_eq!(payload, br#"{"id":7,"name":"seven"}"#);
+
+        let decoded: Body = JsonCodec.decode(&payload).unwrap();
+        assert_eq!(decoded, body());
+
+        assert!(BodyCodec::<Body>::decode(&JsonCodec, b"{\"id\":7}").is_err());
+    }
+}
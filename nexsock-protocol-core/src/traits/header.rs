@@ -1,15 +1,38 @@
 use crate::constants::HEADER_SIZE;
-use crate::error::ProtocolResult;
+use crate::error::{ProtocolError, ProtocolResult};
 use crate::header::Header;
+use alloc::vec::Vec;
 use bytes::{Buf, Bytes, BytesMut};
-use futures::{AsyncRead, AsyncReadExt};
+#[cfg(feature = "std")]
+use tokio::io::{AsyncRead, AsyncReadExt};
 
 pub trait HeaderParser {
     type Serializer: HeaderSerializer;
     type Deserializer: HeaderDeserializer;
+
+    /// Parses back-to-back headers from the front of `bytes` into `out` until fewer than
+    /// [`HEADER_SIZE`] bytes remain, returning how many were parsed.
+    ///
+    /// A partial header at the end is left in `bytes` for the next call. Parsing also stops at the
+    /// first header that fails to parse, leaving it unconsumed.
+    fn parse_many(bytes: &mut Bytes, out: &mut Vec<Header>) -> usize {
+        let mut count = 0;
+
+        while let Some(header) = Self::Deserializer::parse_bytes(bytes) {
+            out.push(header);
+            count += 1;
+        }
+
+        count
+    }
 }
 
 pub trait HeaderDeserializer {
+    /// Parses a header from the start of `bytes`.
+    ///
+    /// Must return `None` rather than panic or read out of bounds when `bytes` is shorter than
+    /// [`HEADER_SIZE`], and must only look at the first [`HEADER_SIZE`] bytes otherwise. Parsers
+    /// reading through raw pointers rely on the length check for soundness.
     fn parse(bytes: &[u8]) -> Option<Header>;
 
     fn parse_bytes(bytes: &mut Bytes) -> Option<Header> {
@@ -25,6 +48,8 @@ pub trait HeaderDeserializer {
         Some(header)
     }
 
+    #[cfg(feature = "std")]
+    #[allow(async_fn_in_trait)]
     async fn read_header<R: AsyncRead + Unpin>(reader: &mut R) -> ProtocolResult<Header> {
         let mut buf = BytesMut::with_capacity(HEADER_SIZE);
         buf.resize(HEADER_SIZE, 0);
@@ -39,4 +64,31 @@ pub trait HeaderDeserializer {
 
 pub trait HeaderSerializer {
     fn serialize(header: &Header) -> [u8; HEADER_SIZE];
+
+    /// Writes the serialized header into the first [`HEADER_SIZE`] bytes of `dst`, leaving the
+    /// rest untouched.
+    ///
+    /// The default goes through [`HeaderSerializer::serialize`]; implementations that can write
+    /// straight into the destination should override it.
+    fn serialize_into(header: &Header, dst: &mut [u8]) -> ProtocolResult<()> {
+        if dst.len() < HEADER_SIZE {
+            return Err(ProtocolError::BufferTooSmall {
+                needed: HEADER_SIZE,
+                got: dst.len(),
+            });
+        }
+
+        dst[..HEADER_SIZE].copy_from_slice(&Self::serialize(header));
+
+        Ok(())
+    }
+
+    /// Appends the serialized header to `buf`, reserving room for it first.
+    ///
+    /// The default copies the result of [`HeaderSerializer::serialize`]; implementations that can
+    /// write straight into the buffer should override it.
+    fn serialize_to_buf(header: &Header, buf: &mut BytesMut) {
+        buf.reserve(HEADER_SIZE);
+        buf.extend_from_slice(&Self::serialize(header));
+    }
 }
@@ -1,12 +1,34 @@
+pub mod allocator;
+pub mod body_codec;
 pub mod header;
 
 use crate::frame::Frame;
+use alloc::string::String;
+use alloc::vec::Vec;
 use bincode::{Decode, Encode};
 
+/// Derives `Encode`, `Decode` and `MessageBody` in one go, optionally setting the id with
+/// `#[message(id = ...)]`. Available with the `derive` feature.
+#[cfg(feature = "derive")]
+pub use nexsock_protocol_derive::MessageBody;
+
 pub trait MessageBody: Encode + Decode<()> {
+    /// The message id this body is sent with, if it has a fixed one.
+    const ID: Option<u8> = None;
+
     fn to_frame<const N: usize>(self, header: [u8; N]) -> Frame<N, Self> {
         Frame::new(header, self)
     }
 }
 
 impl MessageBody for () {}
+
+/// Simple bodies that work without a wrapper type. An already encoded `Bytes` payload can be sent
+/// as is with [`Frame::from_encoded`](crate::frame::Frame::from_encoded).
+macro_rules! impl_message_body {
+    ($($ty:ty),* $(,)?) => {
+        $(impl MessageBody for $ty {})*
+    };
+}
+
+impl_message_body!(bool, u8, u16, u32, u64, i8, i16, i32, i64, String, Vec<u8>);
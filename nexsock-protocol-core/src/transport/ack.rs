@@ -0,0 +1,242 @@
+use super::Transport;
+use crate::constants::HEADER_SIZE;
+use crate::error::{ProtocolError, ProtocolResult};
+use crate::frame::Frame;
+use crate::header::Header;
+use crate::header::standard::StandardHeaderParser;
+use crate::message_flags::MessageFlags;
+use crate::protocol_params::ID_BITS;
+use crate::traits::MessageBody;
+use bincode::{Decode, Encode};
+use bytes::{Bytes, BytesMut};
+use std::collections::VecDeque;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// Message id acknowledgements are sent with unless configured otherwise, the highest id there
+/// is.
+pub const DEFAULT_ACK_ID: u8 = ((1u16 << ID_BITS) - 1) as u8;
+
+/// How long [`AckTransport::send_with_ack`] waits for an acknowledgement by default.
+pub const DEFAULT_ACK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Body of an acknowledgement, naming the sequence number of the frame it acknowledges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Encode, Decode)]
+pub struct Ack {
+    pub sequence_number: u64,
+}
+
+impl MessageBody for Ack {}
+
+impl Ack {
+    /// Builds the frame acknowledging the frame that was read with `header`, sent with `ack_id`,
+    /// e.g. [`DEFAULT_ACK_ID`]. Writing it is up to the caller, which decides whether "received"
+    /// or "processed" is what gets acknowledged.
+    pub fn frame_for(header: &Header, ack_id: u8) -> Frame<{ HEADER_SIZE }, Ack> {
+        let ack_header = Header::new(
+            ack_id,
+            header.version(),
+            MessageFlags::NONE,
+            0,
+            header.sequence_number(),
+        );
+
+        Ack {
+            sequence_number: header.sequence_number(),
+        }
+        .to_frame(ack_header.to_bytes::<StandardHeaderParser>())
+    }
+}
+
+/// Wraps a [`Transport`] to send messages flagged [`MessageFlags::REQUIRES_ACK`] and wait for the
+/// peer to acknowledge them with an [`Ack`] frame.
+///
+/// Frames other than the awaited acknowledgement that arrive while waiting are kept and handed out
+/// by the following reads, in order. An acknowledgement for any other sequence number can only
+/// belong to a message that already timed out, so it is dropped, both while waiting and when
+/// reading.
+pub struct AckTransport<R: AsyncRead + Unpin, W: AsyncWrite + Unpin> {
+    transport: Transport<R, W>,
+    ack_id: u8,
+    ack_timeout: Duration,
+    pending: VecDeque<(Header, BytesMut)>,
+}
+
+impl<R: AsyncRead + Unpin, W: AsyncWrite + Unpin> AckTransport<R, W> {
+    pub fn new(transport: Transport<R, W>) -> Self {
+        Self {
+            transport,
+            ack_id: DEFAULT_ACK_ID,
+            ack_timeout: DEFAULT_ACK_TIMEOUT,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Uses `id` for acknowledgements instead of [`DEFAULT_ACK_ID`]. Both peers have to agree on
+    /// it, and it shouldn't be used by any other message.
+    pub fn with_ack_id(mut self, id: u8) -> Self {
+        self.ack_id = id;
+        self
+    }
+
+    /// Waits `timeout` for an acknowledgement instead of [`DEFAULT_ACK_TIMEOUT`].
+    pub fn with_ack_timeout(mut self, timeout: Duration) -> Self {
+        self.ack_timeout = timeout;
+        self
+    }
+
+    /// Writes `message` flagged [`MessageFlags::REQUIRES_ACK`] and waits for the peer to
+    /// acknowledge its sequence number, returning the header it was sent with.
+    ///
+    /// Fails with [`ProtocolError::AckTimeout`] if no acknowledgement arrives in time. The timeout
+    /// may hit in the middle of a frame, after which the connection should be dropped.
+    pub async fn send_with_ack<T: MessageBody>(
+        &mut self,
+        message: Frame<{ HEADER_SIZE }, T>,
+    ) -> ProtocolResult<Header> {
+        let writer = &mut self.transport.writer;
+
+        let (header, payload) = writer.encode_unstamped(&message)?;
+        let header =
+            writer.stamp_sequence(header.with_flags(header.flags() | MessageFlags::REQUIRES_ACK));
+        let (header, payload) = writer.encrypt(header, payload)?;
+        writer.write_frame(&header, &payload).await?;
+
+        let sequence = header.sequence_number();
+        tokio::time::timeout(self.ack_timeout, self.wait_for_ack(sequence))
+            .await
+            .map_err(|_| ProtocolError::AckTimeout { sequence })??;
+
+        Ok(header)
+    }
+
+    async fn wait_for_ack(&mut self, sequence: u64) -> ProtocolResult<()> {
+        loop {
+            let (header, payload) = self.transport.read_raw_mut().await?;
+
+            if header.id() != self.ack_id {
+                self.pending.push_back((header, payload));
+                continue;
+            }
+
+            let ack: Ack = self.transport.decode_message(&header, &payload)?;
+            if ack.sequence_number == sequence {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Writes the acknowledgement for the frame read with `header`, see [`Ack::frame_for`].
+    pub async fn acknowledge(&mut self, header: &Header) -> ProtocolResult<()> {
+        self.transport
+            .write_message(Ack::frame_for(header, self.ack_id))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Reads the next frame that isn't an acknowledgement, see [`Transport::read_raw`].
+    pub async fn read_raw(&mut self) -> ProtocolResult<(Header, Bytes)> {
+        if let Some((header, payload)) = self.pending.pop_front() {
+            return Ok((header, payload.freeze()));
+        }
+
+        loop {
+            let (header, payload) = self.transport.read_raw().await?;
+
+            if header.id() != self.ack_id {
+                return Ok((header, payload));
+            }
+        }
+    }
+
+    /// Reads the next frame that isn't an acknowledgement and decodes it as `T`, returning its
+    /// header too so it can be passed to [`AckTransport::acknowledge`].
+    pub async fn read_message<T: MessageBody + 'static>(&mut self) -> ProtocolResult<(Header, T)> {
+        let (header, payload) = self.read_raw().await?;
+        let message = self.transport.decode_message(&header, &payload)?;
+
+        Ok((header, message))
+    }
+
+    pub fn inner(&self) -> &Transport<R, W> {
+        &self.transport
+    }
+
+    pub fn inner_mut(&mut self) -> &mut Transport<R, W> {
+        &mut self.transport
+    }
+
+    /// Hands back the wrapped transport. Frames received while waiting for an acknowledgement
+    /// that haven't been read yet are dropped.
+    pub fn into_inner(self) -> Transport<R, W> {
+        self.transport
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Encode, Decode)]
+    struct Request(u32);
+
+    impl MessageBody for Request {}
+
+    fn request(seq: u64, body: u32) -> Frame<{ HEADER_SIZE }, Request> {
+        Request(body).to_frame(
+            Header::new(1, 1, MessageFlags::NONE, 0, seq).to_bytes::<StandardHeaderParser>(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_send_with_ack() {
+        let (client, server) = Transport::pair(1024);
+        let mut client = AckTransport::new(client);
+        let mut server = AckTransport::new(server);
+
+        let peer = tokio::spawn(async move {
+            let (header, Request(body)) = server.read_message().await.unwrap();
+            assert!(header.flags().contains(MessageFlags::REQUIRES_ACK));
+
+            // A stale ack and an unrelated message before the real ack
+            server
+                .inner_mut()
+                .write_message(Ack::frame_for(
+                    &Header::new(1, 1, MessageFlags::NONE, 0, 99),
+                    DEFAULT_ACK_ID,
+                ))
+                .await
+                .unwrap();
+            server
+                .inner_mut()
+                .write_message(request(8, 2))
+                .await
+                .unwrap();
+            server.acknowledge(&header).await.unwrap();
+
+            body
+        });
+
+        let sent = client.send_with_ack(request(7, 1)).await.unwrap();
+        assert_eq!(sent.sequence_number(), 7);
+        assert!(sent.flags().contains(MessageFlags::REQUIRES_ACK));
+
+        // The message that arrived while waiting is still there
+        let (header, Request(body)) = client.read_message().await.unwrap();
+        assert_eq!((header.sequence_number(), body), (8, 2));
+
+        assert_eq!(peer.await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_ack_timeout() {
+        let (client, _server) = Transport::pair(1024);
+        let mut client = AckTransport::new(client).with_ack_timeout(Duration::from_millis(20));
+
+        assert!(matches!(
+            client.send_with_ack(request(3, 1)).await,
+            Err(ProtocolError::AckTimeout { sequence: 3 })
+        ));
+    }
+}
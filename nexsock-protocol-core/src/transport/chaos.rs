@@ -0,0 +1,300 @@
+use super::Transport;
+use crate::constants::HEADER_SIZE;
+use crate::error::ProtocolResult;
+use crate::frame::Frame;
+use crate::header::Header;
+use crate::header::standard::StandardHeaderParser;
+use crate::traits::MessageBody;
+use bytes::Bytes;
+use std::io;
+use std::ops::Range;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// What a [`ChaosTransport`] does to the frames written through it.
+///
+/// Every decision is drawn from a small PRNG seeded with [`ChaosPolicy::with_seed`], so a failing
+/// run can be replayed exactly.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChaosPolicy {
+    drop_rate: f64,
+    duplicate_rate: f64,
+    reorder_rate: f64,
+    latency: Range<Duration>,
+    seed: u64,
+}
+
+impl ChaosPolicy {
+    /// A policy that passes every frame through untouched.
+    pub fn new() -> Self {
+        Self {
+            drop_rate: 0.0,
+            duplicate_rate: 0.0,
+            reorder_rate: 0.0,
+            latency: Duration::ZERO..Duration::ZERO,
+            seed: 0x6E65_7873_6F63_6B21,
+        }
+    }
+
+    /// Silently drops each frame with probability `rate`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rate` is not within `0.0..=1.0`, as do the other rates.
+    pub fn with_drop_rate(mut self, rate: f64) -> Self {
+        self.drop_rate = check_rate(rate);
+        self
+    }
+
+    /// Writes each frame twice with probability `rate`.
+    pub fn with_duplicate_rate(mut self, rate: f64) -> Self {
+        self.duplicate_rate = check_rate(rate);
+        self
+    }
+
+    /// Holds each frame back with probability `rate` and writes it after the next one instead.
+    pub fn with_reorder_rate(mut self, rate: f64) -> Self {
+        self.reorder_rate = check_rate(rate);
+        self
+    }
+
+    /// Delays each frame by a duration drawn uniformly from `latency` before writing it.
+    pub fn with_latency(mut self, latency: Range<Duration>) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+}
+
+impl Default for ChaosPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn check_rate(rate: f64) -> f64 {
+    assert!(
+        (0.0..=1.0).contains(&rate),
+        "rate must be between 0 and 1, got {rate}"
+    );
+
+    rate
+}
+
+/// SplitMix64, which is plenty for deciding the fate of frames.
+#[derive(Debug)]
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Returns `true` with probability `rate`.
+    fn chance(&mut self, rate: f64) -> bool {
+        let unit = (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+
+        unit < rate
+    }
+
+    fn duration_in(&mut self, range: &Range<Duration>) -> Duration {
+        let span = range.end.saturating_sub(range.start).as_nanos() as u64;
+        if span == 0 {
+            return range.start;
+        }
+
+        range.start + Duration::from_nanos(self.next_u64() % span)
+    }
+}
+
+/// Wraps a [`Transport`] and drops, delays, duplicates or reorders the frames written through it
+/// according to a [`ChaosPolicy`], for exercising acknowledgement, retransmission and flow control
+/// logic without a lossy network. Available with the `testing` feature.
+///
+/// Only whole frames are affected, never their bytes, so the peer always sees a valid stream.
+/// Reading isn't touched; use [`ChaosTransport::inner_mut`] for it.
+pub struct ChaosTransport<R: AsyncRead + Unpin, W: AsyncWrite + Unpin> {
+    transport: Transport<R, W>,
+    policy: ChaosPolicy,
+    rng: Rng,
+    held: Option<(Header, Vec<u8>)>,
+}
+
+impl<R: AsyncRead + Unpin, W: AsyncWrite + Unpin> ChaosTransport<R, W> {
+    pub fn new(transport: Transport<R, W>, policy: ChaosPolicy) -> Self {
+        let rng = Rng(policy.seed);
+
+        Self {
+            transport,
+            policy,
+            rng,
+            held: None,
+        }
+    }
+
+    /// Encodes `message` like [`Transport::write_message`] and then subjects it to the policy.
+    /// Returns the header it was encoded with, whether or not it was actually sent.
+    pub async fn write_message<T: MessageBody>(
+        &mut self,
+        message: Frame<{ HEADER_SIZE }, T>,
+    ) -> ProtocolResult<Header> {
+        let (header, payload) = self.transport.encode_message(&message)?;
+        self.send(header, payload).await?;
+
+        Ok(header)
+    }
+
+    /// Subjects an already encoded frame to the policy, see [`Transport::write_raw`].
+    pub async fn write_raw(&mut self, frame: &Frame<{ HEADER_SIZE }, Bytes>) -> ProtocolResult<()> {
+        let header = Header::parse::<StandardHeaderParser>(&frame.header()).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "Failed to parse frame header")
+        })?;
+
+        self.send(header, frame.body().to_vec()).await
+    }
+
+    async fn send(&mut self, header: Header, payload: Vec<u8>) -> ProtocolResult<()> {
+        if self.rng.chance(self.policy.drop_rate) {
+            return Ok(());
+        }
+
+        let delay = self.rng.duration_in(&self.policy.latency);
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+
+        let copies = if self.rng.chance(self.policy.duplicate_rate) {
+            2
+        } else {
+            1
+        };
+
+        if self.held.is_none() && self.rng.chance(self.policy.reorder_rate) {
+            self.held = Some((header, payload));
+
+            return Ok(());
+        }
+
+        for _ in 0..copies {
+            self.transport.write_frame(&header, &payload).await?;
+        }
+
+        self.release_held().await
+    }
+
+    /// Writes the frame being held back for reordering, if any, so it isn't lost when no further
+    /// frame follows.
+    pub async fn release_held(&mut self) -> ProtocolResult<()> {
+        if let Some((header, payload)) = self.held.take() {
+            self.transport.write_frame(&header, &payload).await?;
+        }
+
+        Ok(())
+    }
+
+    pub fn inner(&self) -> &Transport<R, W> {
+        &self.transport
+    }
+
+    pub fn inner_mut(&mut self) -> &mut Transport<R, W> {
+        &mut self.transport
+    }
+
+    /// Hands back the wrapped transport. A frame still held back for reordering is dropped.
+    pub fn into_inner(self) -> Transport<R, W> {
+        self.transport
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ProtocolError;
+    use crate::message_flags::MessageFlags;
+    use crate::transport::tests::{MockReader, MockWriter};
+
+    fn frame(seq: u64) -> Frame<{ HEADER_SIZE }, ()> {
+        ().to_frame(
+            Header::new(1, 1, MessageFlags::NONE, 0, seq).to_bytes::<StandardHeaderParser>(),
+        )
+    }
+
+    async fn send_all(policy: ChaosPolicy, count: u64) -> Vec<u8> {
+        let transport = Transport::new(MockReader::new(Vec::new()), MockWriter::new());
+        let mut chaos = ChaosTransport::new(transport, policy);
+
+        for seq in 0..count {
+            chaos.write_message(frame(seq)).await.unwrap();
+        }
+        chaos.release_held().await.unwrap();
+
+        chaos.inner().writer().written_data().to_vec()
+    }
+
+    async fn read_sequence_numbers(written: Vec<u8>) -> Vec<u64> {
+        let mut reader = Transport::new(MockReader::new(written), MockWriter::new());
+
+        let mut sequence_numbers = Vec::new();
+        while let Ok((header, _)) = reader.read_raw().await {
+            sequence_numbers.push(header.sequence_number());
+        }
+
+        sequence_numbers
+    }
+
+    #[tokio::test]
+    async fn test_drop_everything() {
+        let written = send_all(ChaosPolicy::new().with_drop_rate(1.0), 10).await;
+
+        assert!(written.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_duplicates_filtered_by_sequence_tracking() {
+        let written = send_all(ChaosPolicy::new().with_duplicate_rate(1.0), 5).await;
+        assert_eq!(
+            read_sequence_numbers(written.clone()).await,
+            [0, 0, 1, 1, 2, 2, 3, 3, 4, 4]
+        );
+
+        let mut reader = Transport::new(MockReader::new(written), MockWriter::new())
+            .with_sequence_tracking(true);
+        let mut delivered = Vec::new();
+        loop {
+            match reader.read_raw().await {
+                Ok((header, _)) => delivered.push(header.sequence_number()),
+                Err(ProtocolError::SequenceRegression { .. }) => continue,
+                Err(_) => break,
+            }
+        }
+
+        assert_eq!(delivered, [0, 1, 2, 3, 4]);
+    }
+
+    #[tokio::test]
+    async fn test_reorder() {
+        let written = send_all(ChaosPolicy::new().with_reorder_rate(1.0), 5).await;
+
+        assert_eq!(read_sequence_numbers(written).await, [1, 0, 3, 2, 4]);
+    }
+
+    #[tokio::test]
+    async fn test_partial_drop_is_reproducible() {
+        let policy = ChaosPolicy::new().with_drop_rate(0.5).with_seed(7);
+
+        let first = read_sequence_numbers(send_all(policy.clone(), 100).await).await;
+        let second = read_sequence_numbers(send_all(policy, 100).await).await;
+
+        assert_eq!(first, second);
+        assert!(first.len() > 20 && first.len() < 80);
+    }
+}
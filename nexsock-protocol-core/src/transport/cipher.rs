@@ -0,0 +1,79 @@
+use crate::error::{ProtocolError, ProtocolResult};
+use crate::header::Header;
+use crate::header::standard::StandardHeaderParser;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, KeyInit, Nonce};
+use std::io;
+
+/// Size of the nonce prepended to every encrypted payload.
+pub(crate) const NONCE_SIZE: usize = 12;
+/// Size of the authentication tag following the ciphertext.
+pub(crate) const TAG_SIZE: usize = 16;
+
+/// ChaCha20-Poly1305 state for one half of a transport, see
+/// [`Transport::with_cipher`](super::Transport::with_cipher).
+///
+/// Every frame gets a fresh random nonce, which is sent along with it, so nothing the caller does
+/// with sequence numbers can make one repeat.
+#[derive(Clone)]
+pub(crate) struct Cipher {
+    aead: ChaCha20Poly1305,
+}
+
+impl Cipher {
+    pub(crate) fn new(key: &[u8; 32]) -> Self {
+        Self {
+            aead: ChaCha20Poly1305::new(Key::from_slice(key)),
+        }
+    }
+
+    /// Encrypts `payload`, returning the nonce followed by the ciphertext and tag.
+    ///
+    /// `header` must be the header the frame is sent with, i.e. already flagged as encrypted and
+    /// carrying the final payload length, since it's authenticated along with the payload.
+    pub(crate) fn seal(&self, header: &Header, payload: &[u8]) -> ProtocolResult<Vec<u8>> {
+        let mut nonce = [0u8; NONCE_SIZE];
+        OsRng.fill_bytes(&mut nonce);
+
+        let aad = header.to_bytes::<StandardHeaderParser>();
+        let ciphertext = self
+            .aead
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: payload,
+                    aad: &aad,
+                },
+            )
+            .map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidInput, "payload too large to encrypt")
+            })?;
+
+        let mut sealed = Vec::with_capacity(NONCE_SIZE + ciphertext.len());
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+
+        Ok(sealed)
+    }
+
+    /// Checks and decrypts a payload produced by [`Cipher::seal`] for the frame with `header`.
+    pub(crate) fn open(&self, header: &Header, payload: &[u8]) -> ProtocolResult<Vec<u8>> {
+        if payload.len() < NONCE_SIZE + TAG_SIZE {
+            return Err(ProtocolError::Decryption);
+        }
+
+        let (nonce, ciphertext) = payload.split_at(NONCE_SIZE);
+        let aad = header.to_bytes::<StandardHeaderParser>();
+
+        self.aead
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: &aad,
+                },
+            )
+            .map_err(|_| ProtocolError::Decryption)
+    }
+}
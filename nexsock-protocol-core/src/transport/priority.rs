@@ -0,0 +1,212 @@
+use super::TransportWriter;
+use crate::constants::HEADER_SIZE;
+use crate::error::ProtocolResult;
+use crate::frame::Frame;
+use crate::header::Header;
+use crate::traits::MessageBody;
+use std::collections::{BTreeMap, VecDeque};
+use tokio::io::AsyncWrite;
+
+/// Default number of frames written before a waiting frame gains one priority level, see
+/// [`PriorityWriter::with_aging`].
+pub const DEFAULT_AGING: u64 = 8;
+
+struct Queued {
+    /// Position in the overall enqueue order, used to break ties.
+    order: u64,
+    /// Value of the written-frames clock when the frame was enqueued.
+    enqueued_at: u64,
+    header: Header,
+    payload: Vec<u8>,
+}
+
+/// Queues frames with a priority and writes them highest priority first, over the write half of a
+/// [`Transport`](super::Transport).
+///
+/// Priorities only affect the order frames leave this end in; they aren't sent. To keep a steady
+/// stream of high priority frames from starving the rest, a waiting frame gains one priority level
+/// for every [aging](PriorityWriter::with_aging) frames written before it. Frames of equal
+/// effective priority go out in the order they were enqueued.
+///
+/// Bodies are encoded when enqueued, but sequence numbers are stamped (and payloads encrypted) when
+/// the frame is written, so [automatic sequence numbers](super::Transport::with_auto_sequence)
+/// still go out in order.
+pub struct PriorityWriter<W: AsyncWrite + Unpin> {
+    writer: TransportWriter<W>,
+    queues: BTreeMap<u8, VecDeque<Queued>>,
+    aging: u64,
+    enqueued: u64,
+    written: u64,
+}
+
+impl<W: AsyncWrite + Unpin> PriorityWriter<W> {
+    pub fn new(writer: TransportWriter<W>) -> Self {
+        Self {
+            writer,
+            queues: BTreeMap::new(),
+            aging: DEFAULT_AGING,
+            enqueued: 0,
+            written: 0,
+        }
+    }
+
+    /// Raises waiting frames by one priority level every `frames` frames written. Defaults to
+    /// [`DEFAULT_AGING`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `frames` is zero.
+    pub fn with_aging(mut self, frames: u64) -> Self {
+        assert!(frames > 0, "aging must be at least one frame");
+
+        self.aging = frames;
+        self
+    }
+
+    /// Encodes `frame` and queues it with `priority`, higher going first.
+    pub fn enqueue<T: MessageBody>(
+        &mut self,
+        priority: u8,
+        frame: Frame<{ HEADER_SIZE }, T>,
+    ) -> ProtocolResult<()> {
+        let (header, payload) = self.writer.encode_unstamped(&frame)?;
+
+        self.queues.entry(priority).or_default().push_back(Queued {
+            order: self.enqueued,
+            enqueued_at: self.written,
+            header,
+            payload,
+        });
+        self.enqueued += 1;
+
+        Ok(())
+    }
+
+    /// Writes the queued frame with the highest effective priority, returning the header it was
+    /// sent with, or `None` if nothing is queued.
+    pub async fn write_next(&mut self) -> ProtocolResult<Option<Header>> {
+        let Some(priority) = self.next_priority() else {
+            return Ok(None);
+        };
+
+        let queue = self
+            .queues
+            .get_mut(&priority)
+            .expect("picked an empty priority");
+        let queued = queue.pop_front().expect("picked an empty priority");
+        if queue.is_empty() {
+            self.queues.remove(&priority);
+        }
+
+        let header = self.writer.stamp_sequence(queued.header);
+        let (header, payload) = self.writer.encrypt(header, queued.payload)?;
+        self.writer.write_frame(&header, &payload).await?;
+        self.written += 1;
+
+        Ok(Some(header))
+    }
+
+    /// Writes queued frames until none are left.
+    pub async fn drain(&mut self) -> ProtocolResult<()> {
+        while self.write_next().await?.is_some() {}
+
+        Ok(())
+    }
+
+    /// Priority level whose oldest frame should be written next.
+    ///
+    /// Only the front of each level needs looking at, since it's the oldest frame there and so
+    /// has aged the most.
+    fn next_priority(&self) -> Option<u8> {
+        self.queues
+            .iter()
+            .filter_map(|(&priority, queue)| {
+                let front = queue.front()?;
+                let age = (self.written - front.enqueued_at) / self.aging;
+
+                Some((priority as u64 + age, front.order, priority))
+            })
+            .max_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)))
+            .map(|(_, _, priority)| priority)
+    }
+
+    /// Number of frames waiting to be written.
+    pub fn len(&self) -> usize {
+        self.queues.values().map(VecDeque::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queues.is_empty()
+    }
+
+    /// Hands back the write half. Frames still queued are dropped.
+    pub fn into_inner(self) -> TransportWriter<W> {
+        self.writer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::standard::StandardHeaderParser;
+    use crate::message_flags::MessageFlags;
+    use crate::transport::tests::{MockReader, MockWriter};
+    use crate::transport::{Transport, TransportReader};
+
+    fn frame(id: u8) -> Frame<{ HEADER_SIZE }, ()> {
+        ().to_frame(Header::new(id, 1, MessageFlags::NONE, 0, 0).to_bytes::<StandardHeaderParser>())
+    }
+
+    async fn written_ids(writer: PriorityWriter<MockWriter>) -> Vec<u8> {
+        let written = writer.into_inner().writer.written_data().to_vec();
+        let mut reader = TransportReader::new(MockReader::new(written));
+
+        let mut ids = Vec::new();
+        while let Ok((header, _)) = reader.read_raw().await {
+            ids.push(header.id());
+        }
+
+        ids
+    }
+
+    #[tokio::test]
+    async fn test_high_priority_first() {
+        let (_, writer) = Transport::new(MockReader::new(Vec::new()), MockWriter::new()).split();
+        let mut writer = PriorityWriter::new(writer);
+
+        writer.enqueue(0, frame(1)).unwrap();
+        writer.enqueue(0, frame(2)).unwrap();
+        writer.enqueue(5, frame(3)).unwrap();
+        writer.enqueue(5, frame(4)).unwrap();
+        assert_eq!(writer.len(), 4);
+
+        writer.drain().await.unwrap();
+        assert!(writer.is_empty());
+
+        assert_eq!(written_ids(writer).await, [3, 4, 1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_aging_prevents_starvation() {
+        let (_, writer) = Transport::new(MockReader::new(Vec::new()), MockWriter::new())
+            .with_auto_sequence(true)
+            .split();
+        let mut writer = PriorityWriter::new(writer).with_aging(1);
+
+        writer.enqueue(0, frame(1)).unwrap();
+
+        // A fresh high priority frame arrives before every write, yet the low priority one still
+        // gets through once it has waited long enough.
+        let mut sequence_numbers = Vec::new();
+        for id in 10..15 {
+            writer.enqueue(2, frame(id)).unwrap();
+
+            let header = writer.write_next().await.unwrap().unwrap();
+            sequence_numbers.push(header.sequence_number());
+        }
+        writer.drain().await.unwrap();
+
+        assert_eq!(sequence_numbers, [0, 1, 2, 3, 4]);
+        assert_eq!(written_ids(writer).await, [10, 11, 1, 12, 13, 14]);
+    }
+}
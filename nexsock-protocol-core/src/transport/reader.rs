@@ -0,0 +1,1008 @@
+use super::{
+    BorrowedMessage, HeaderLayout, MAX_HEADER_SIZE, TransportWriter, WireParams, check_magic,
+    checksum, trace,
+};
+use crate::constants::HEADER_SIZE;
+use crate::error::{ProtocolError, ProtocolResult};
+use crate::frame::FrameRef;
+use crate::header::Header;
+use crate::header::compact::{COMPACT_HEADER_SIZE, CompactHeaderParser};
+use crate::header::little_endian::LittleEndianHeaderParser;
+use crate::header::standard::StandardHeaderParser;
+use crate::header::varint::{VARINT_PREFIX_SIZE, VarintHeaderParser, varint_len};
+use crate::message_flags::MessageFlags;
+use crate::pool::{BufferPool, PooledBuffer};
+use crate::registry::{DynMessage, MessageRegistry};
+use crate::schema::SchemaChain;
+use crate::traits::allocator::{BufferAllocator, DefaultBufferAllocator};
+use crate::traits::body_codec::{BincodeCodec, BodyCodec};
+use bincode::{BorrowDecode, Decode};
+use bytes::{Buf, Bytes, BytesMut};
+use futures::Stream;
+use std::borrow::Cow;
+use std::io;
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite};
+
+/// The read half of a [`Transport`](super::Transport), see
+/// [`Transport::split`](super::Transport::split).
+pub struct TransportReader<R: AsyncRead + Unpin, C = BincodeCodec> {
+    pub(super) reader: R,
+    pub(super) payload_alignment: usize,
+    pub(super) allocator: Arc<dyn BufferAllocator>,
+    pub(super) buffer_pool: Option<BufferPool>,
+    pub(super) read_offset: u64,
+    pub(super) header_layout: HeaderLayout,
+    pub(super) params: WireParams,
+    pub(super) type_registry: Option<MessageRegistry>,
+    pub(super) max_payload_len: u32,
+    pub(super) supported_version: Option<u8>,
+    pub(super) borrow_buffer: BytesMut,
+    pub(super) sequence_tracking: bool,
+    pub(super) last_sequence: Option<u64>,
+    pub(super) gap_callback: Option<Box<dyn FnMut(u64, u64) + Send>>,
+    pub(super) read_timeout: Option<Duration>,
+    /// The last header read and its length, as it was on the wire, which is what a checksum
+    /// trailer covers.
+    pub(super) last_header: ([u8; MAX_HEADER_SIZE], usize),
+    #[cfg(feature = "encryption")]
+    pub(super) cipher: Option<super::cipher::Cipher>,
+    pub(super) codec: C,
+}
+
+impl<R: AsyncRead + Unpin> TransportReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            payload_alignment: 1,
+            allocator: Arc::new(DefaultBufferAllocator),
+            buffer_pool: None,
+            read_offset: 0,
+            header_layout: HeaderLayout::Standard,
+            params: WireParams::default(),
+            type_registry: None,
+            max_payload_len: super::DEFAULT_MAX_PAYLOAD_LEN,
+            supported_version: None,
+            borrow_buffer: BytesMut::new(),
+            sequence_tracking: false,
+            last_sequence: None,
+            gap_callback: None,
+            read_timeout: None,
+            last_header: ([0; MAX_HEADER_SIZE], 0),
+            #[cfg(feature = "encryption")]
+            cipher: None,
+            codec: BincodeCodec,
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin, C> TransportReader<R, C> {
+    /// Swaps the body codec, keeping the rest of the configuration.
+    pub(super) fn with_codec<C2>(self, codec: C2) -> TransportReader<R, C2> {
+        TransportReader {
+            reader: self.reader,
+            payload_alignment: self.payload_alignment,
+            allocator: self.allocator,
+            buffer_pool: self.buffer_pool,
+            read_offset: self.read_offset,
+            header_layout: self.header_layout,
+            params: self.params,
+            type_registry: self.type_registry,
+            max_payload_len: self.max_payload_len,
+            supported_version: self.supported_version,
+            borrow_buffer: self.borrow_buffer,
+            sequence_tracking: self.sequence_tracking,
+            last_sequence: self.last_sequence,
+            gap_callback: self.gap_callback,
+            read_timeout: self.read_timeout,
+            last_header: self.last_header,
+            #[cfg(feature = "encryption")]
+            cipher: self.cipher,
+            codec,
+        }
+    }
+
+    /// Total number of bytes consumed from the reader since construction.
+    ///
+    /// Handy for correlating a framing error with an offset in a packet capture.
+    #[inline]
+    pub fn read_offset(&self) -> u64 {
+        self.read_offset
+    }
+
+    /// Reads the next frame and decodes its body as `T`. A fragmented message is reassembled
+    /// first, see [`TransportWriter::write_stream`](super::TransportWriter::write_stream).
+    ///
+    /// Frames without a payload decode `T` from an empty buffer, which works for `()`. With a type
+    /// registry configured, the whole frame is consumed before `T` is checked, so a mismatch
+    /// leaves the stream positioned at the next frame.
+    pub async fn read_message<T: 'static>(&mut self) -> ProtocolResult<T>
+    where
+        C: BodyCodec<T>,
+    {
+        let read = async {
+            let (header, payload) = self.read_raw_mut().await?;
+            trace::record_header(&header);
+
+            let message = self.decode_message(&header, &payload)?;
+
+            #[cfg(feature = "tracing")]
+            tracing::debug!("message read");
+
+            Ok(message)
+        };
+
+        #[cfg(feature = "tracing")]
+        let read = tracing::Instrument::instrument(read, trace::message_span!("read_message"));
+
+        read.await
+    }
+
+    /// Like [`TransportReader::read_message`], but returns `None` if the stream ends cleanly, i.e.
+    /// before the first byte of the next frame. Running out of data anywhere inside a frame is
+    /// still an error.
+    pub async fn try_read_message<T: 'static>(&mut self) -> ProtocolResult<Option<T>>
+    where
+        C: BodyCodec<T>,
+    {
+        let mut magic = [0u8; 4];
+
+        if self.read_some(&mut magic[..1]).await? == 0 {
+            return Ok(None);
+        }
+
+        self.read_exact(&mut magic[1..]).await?;
+        check_magic(&magic, &self.params.magic)?;
+
+        let (header, payload) = self.read_frame_after_magic().await?;
+
+        self.decode_message(&header, &payload).map(Some)
+    }
+
+    /// Turns the reader into a [`Stream`] of decoded messages.
+    ///
+    /// The stream ends when the underlying reader does, as long as that happens between frames.
+    /// Any error, including the reader ending mid-frame, is yielded once and then ends the stream,
+    /// since the position of the next frame is unknown at that point.
+    pub fn into_stream<T: 'static>(self) -> impl Stream<Item = ProtocolResult<T>>
+    where
+        C: BodyCodec<T>,
+    {
+        futures::stream::unfold(Some(self), |reader| async move {
+            let mut reader = reader?;
+
+            match reader.try_read_message().await {
+                Ok(Some(message)) => Some((Ok(message), Some(reader))),
+                Ok(None) => None,
+                Err(err) => Some((Err(err), None)),
+            }
+        })
+    }
+
+    /// Reads the next frame and decodes its body as the first of the candidate types in `S` that
+    /// fits, newest first, returning which one it was. See [`schema`](crate::schema).
+    ///
+    /// The type registry isn't consulted, since the point is to accept several types per id.
+    /// Schema chains are always decoded with bincode, whatever the configured codec.
+    pub async fn read_any<S: SchemaChain>(&mut self) -> ProtocolResult<S::Variant> {
+        let (header, payload) = self.read_raw_mut().await?;
+
+        S::decode(&self.open_payload(&header, &payload)?)
+    }
+
+    /// Reads the next frame and decodes its body as whatever type the type registry has for its id,
+    /// for dispatching on the id in a server loop. See [`MessageRegistry::decode`].
+    ///
+    /// Fails with [`ProtocolError::UnregisteredId`] if the id has no registration or no registry
+    /// is configured. The frame is consumed either way.
+    pub async fn read_registered(&mut self) -> ProtocolResult<(Header, DynMessage)> {
+        let (header, payload) = self.read_raw_mut().await?;
+
+        let registry = self
+            .type_registry
+            .as_ref()
+            .ok_or(ProtocolError::UnregisteredId { id: header.id() })?;
+        let body = registry.decode(header.id(), &self.open_payload(&header, &payload)?)?;
+
+        Ok((header, body))
+    }
+
+    /// Reads the magic and header of the next frame, leaving its payload in the stream so the
+    /// caller can decide what to do with it based on the header.
+    ///
+    /// The payload must then be consumed with [`TransportReader::read_body`] or
+    /// [`TransportReader::skip_payload`] before reading anything else. Fragmented messages aren't
+    /// reassembled here; each fragment comes back as its own frame.
+    pub async fn read_header_only(&mut self) -> ProtocolResult<Header> {
+        self.read_magic().await?;
+
+        let header = self.read_header().await?;
+        self.track_sequence(&header)?;
+
+        Ok(header)
+    }
+
+    /// Reads the payload following a header returned by [`TransportReader::read_header_only`] and
+    /// decodes it as `T`.
+    pub async fn read_body<T: 'static>(&mut self, header: &Header) -> ProtocolResult<T>
+    where
+        C: BodyCodec<T>,
+    {
+        let mut payload = self.alloc_payload(self.frame_payload_len(header)?);
+        self.read_exact(&mut payload).await?;
+        self.read_checksum(header, &payload).await?;
+
+        self.decode_message(header, &payload)
+    }
+
+    /// Discards the payload following a header returned by
+    /// [`TransportReader::read_header_only`], along with its checksum trailer if it has one, which
+    /// isn't verified.
+    ///
+    /// The payload is read through a small stack buffer, so skipping a large one doesn't allocate.
+    pub async fn skip_payload(&mut self, header: &Header) -> ProtocolResult<()> {
+        let mut remaining = self.frame_payload_len(header)?;
+        if header.flags().contains(MessageFlags::HAS_CHECKSUM) {
+            remaining += 4;
+        }
+
+        let mut scratch = [0u8; 1024];
+        while remaining > 0 {
+            let chunk = remaining.min(scratch.len());
+            self.read_exact(&mut scratch[..chunk]).await?;
+            remaining -= chunk;
+        }
+
+        Ok(())
+    }
+
+    /// Reads the next frame, whose body must be an encoded `Vec<Item>`, and decodes its items one
+    /// at a time as the returned stream is polled.
+    ///
+    /// The whole payload is read before the first item is yielded, but the items are never
+    /// collected, so only one of them is alive at a time. A type registry, if configured, is
+    /// checked against `Vec<Item>`. The stream ends after the last item or the first error.
+    ///
+    /// Items are always decoded with bincode, whatever the configured codec.
+    pub fn read_items<Item: Decode<()> + 'static>(
+        &mut self,
+    ) -> impl Stream<Item = ProtocolResult<Item>> + '_ {
+        futures::stream::unfold(ItemsState::Unread(self), |state| async move {
+            let (mut payload, remaining) = match state {
+                ItemsState::Unread(reader) => match reader.read_items_payload::<Item>().await {
+                    Ok(read) => read,
+                    Err(err) => return Some((Err(err), ItemsState::Done)),
+                },
+                ItemsState::Decoding { payload, remaining } => (payload, remaining),
+                ItemsState::Done => return None,
+            };
+
+            if remaining == 0 {
+                return None;
+            }
+
+            let config = bincode::config::standard().with_big_endian();
+            match bincode::decode_from_slice::<Item, _>(&payload, config) {
+                Ok((item, used)) => {
+                    payload.advance(used);
+
+                    Some((
+                        Ok(item),
+                        ItemsState::Decoding {
+                            payload,
+                            remaining: remaining - 1,
+                        },
+                    ))
+                }
+                Err(err) => Some((Err(err.into()), ItemsState::Done)),
+            }
+        })
+    }
+
+    /// Reads the payload of a `Vec<Item>` frame, returning it positioned at the first item along
+    /// with the number of items.
+    async fn read_items_payload<Item: 'static>(&mut self) -> ProtocolResult<(Bytes, u64)> {
+        let (header, payload) = self.read_raw_mut().await?;
+
+        if let Some(registry) = &self.type_registry {
+            registry.check::<Vec<Item>>(header.id())?;
+        }
+
+        let mut payload = if header
+            .flags()
+            .intersects(MessageFlags::COMPRESSED | MessageFlags::ENCRYPTED)
+        {
+            Bytes::from(self.open_payload(&header, &payload)?.into_owned())
+        } else {
+            payload.freeze()
+        };
+
+        let config = bincode::config::standard().with_big_endian();
+        let (count, used) = bincode::decode_from_slice::<u64, _>(&payload, config)?;
+        payload.advance(used);
+
+        Ok((payload, count))
+    }
+
+    /// Like [`TransportReader::read_message`], but with separate timeouts before and during a frame.
+    ///
+    /// `idle` bounds the wait for the first byte of the next frame, so a quiet peer can take as
+    /// long as it likes to send its next request. Once that byte arrives, the rest of the frame has
+    /// to follow within `active`, which catches a peer stalling mid-frame.
+    ///
+    /// Hitting the `idle` timeout leaves the stream untouched. After
+    /// [`ProtocolError::FrameTimeout`] part of a frame has already been consumed, so the
+    /// connection should be dropped.
+    pub async fn read_message_split_timeout<T: 'static>(
+        &mut self,
+        idle: Duration,
+        active: Duration,
+    ) -> ProtocolResult<T>
+    where
+        C: BodyCodec<T>,
+    {
+        let mut magic = [0u8; 4];
+
+        tokio::time::timeout(idle, self.read_exact(&mut magic[..1]))
+            .await
+            .map_err(|_| ProtocolError::IdleTimeout)??;
+
+        tokio::time::timeout(active, async {
+            self.read_exact(&mut magic[1..]).await?;
+            check_magic(&magic, &self.params.magic)?;
+
+            let (header, payload) = self.read_frame_after_magic().await?;
+
+            self.decode_message(&header, &payload)
+        })
+        .await
+        .map_err(|_| ProtocolError::FrameTimeout)?
+    }
+
+    /// Reads the next frame into a buffer owned by the transport and decodes a `T` that borrows
+    /// straight from it, so fields like `&str` or `&[u8]` are never copied.
+    ///
+    /// The buffer is reused across calls; the returned message borrows the transport, which keeps
+    /// the buffer alive (and unchanged) for as long as the message is around. Borrowed messages are
+    /// always decoded with bincode, whatever the configured codec.
+    pub async fn read_borrowed<'a, T: BorrowDecode<'a, ()>>(
+        &'a mut self,
+    ) -> ProtocolResult<BorrowedMessage<'a, T>> {
+        let header = self.read_into_borrow_buffer().await?;
+
+        if header
+            .flags()
+            .intersects(MessageFlags::COMPRESSED | MessageFlags::ENCRYPTED)
+        {
+            let opened = BytesMut::from(&self.open_payload(&header, &self.borrow_buffer)?[..]);
+            self.borrow_buffer = opened;
+        }
+
+        let config = bincode::config::standard().with_big_endian();
+        let (body, _) = bincode::borrow_decode_from_slice(&self.borrow_buffer, config)?;
+
+        Ok(BorrowedMessage {
+            header,
+            body,
+            _buffer: std::marker::PhantomData,
+        })
+    }
+
+    /// Reads the next frame into a buffer owned by the transport and returns it with its payload
+    /// exactly as it was on the wire, i.e. not decoded, decompressed or decrypted.
+    ///
+    /// Like [`TransportReader::read_borrowed`], the buffer is reused across calls, so forwarding
+    /// frames this way doesn't allocate or copy per frame once the buffer has grown. The header is
+    /// returned in the standard layout whichever layout was read.
+    pub async fn read_frame_raw(&mut self) -> ProtocolResult<FrameRef<'_, { HEADER_SIZE }>> {
+        let header = self.read_into_borrow_buffer().await?;
+
+        Ok(FrameRef::new(
+            header.to_bytes::<StandardHeaderParser>(),
+            header,
+            &self.borrow_buffer,
+        ))
+    }
+
+    /// Reads the next frame's payload into the reusable borrow buffer, returning its header.
+    async fn read_into_borrow_buffer(&mut self) -> ProtocolResult<Header> {
+        self.read_magic().await?;
+
+        let header = self.read_header().await?;
+        let payload_len = self.frame_payload_len(&header)?;
+
+        let mut buffer = std::mem::take(&mut self.borrow_buffer);
+        buffer.clear();
+        buffer.resize(payload_len, 0);
+
+        let mut read = self.read_exact(&mut buffer).await;
+        if read.is_ok() {
+            read = self.read_checksum(&header, &buffer).await.map(|_| ());
+        }
+        if read.is_ok() {
+            read = self.track_sequence(&header);
+        }
+        let header = match read {
+            Ok(()) => self.reassemble(header, &mut buffer).await,
+            Err(err) => Err(err),
+        };
+        self.borrow_buffer = buffer;
+
+        header
+    }
+
+    /// Reads the next frame without decoding its body, returning the parsed header together with
+    /// the raw payload bytes.
+    pub async fn read_raw(&mut self) -> ProtocolResult<(Header, Bytes)> {
+        let (header, payload) = self.read_raw_mut().await?;
+
+        Ok((header, payload.freeze()))
+    }
+
+    /// Like [`TransportReader::read_raw`], but hands back the payload as an owned [`BytesMut`] so it can
+    /// be transformed in place (e.g. decrypted and then decompressed) without further copies.
+    pub async fn read_raw_mut(&mut self) -> ProtocolResult<(Header, BytesMut)> {
+        self.read_magic().await?;
+
+        self.read_frame_after_magic().await
+    }
+
+    /// Like [`TransportReader::read_raw_mut`], but reads the payload into a buffer taken from the
+    /// configured [`BufferPool`]. The buffer goes back to the pool once the returned guard is
+    /// dropped. Without a pool this falls back to a regular allocation.
+    pub async fn read_pooled(&mut self) -> ProtocolResult<(Header, PooledBuffer)> {
+        self.read_magic().await?;
+
+        let header = self.read_header().await?;
+        let payload_len = self.frame_payload_len(&header)?;
+
+        let mut payload = match &self.buffer_pool {
+            Some(pool) => pool.acquire(payload_len),
+            None => PooledBuffer::unpooled(self.alloc_payload(payload_len)),
+        };
+        self.read_exact(&mut payload).await?;
+        self.read_checksum(&header, &payload).await?;
+        self.track_sequence(&header)?;
+
+        let header = self.reassemble(header, &mut payload).await?;
+
+        Ok((header, payload))
+    }
+
+    /// Reads and decodes the next frame while also returning the exact bytes it occupied on the
+    /// wire (magic, header, payload and checksum trailer if any), e.g. for archiving without
+    /// re-serializing.
+    ///
+    /// A fragmented message has no single body to decode on the wire, so its first fragment fails
+    /// with [`ProtocolError::UnexpectedFragment`], leaving the rest of the fragments unread.
+    pub async fn read_frame_with_wire<T>(&mut self) -> ProtocolResult<(Header, T, Bytes)>
+    where
+        C: BodyCodec<T>,
+    {
+        let (header, wire, payload) = self.read_wire().await?;
+        if header.flags().contains(MessageFlags::FRAGMENTED) {
+            return Err(ProtocolError::UnexpectedFragment {
+                sequence: header.sequence_number(),
+            });
+        }
+        let body = self.decode_payload(&header, &wire[payload])?;
+
+        Ok((header, body, wire))
+    }
+
+    /// Reads the next frame and writes the exact bytes it occupied on the wire to `dest`, returning
+    /// its header.
+    ///
+    /// Nothing is re-encoded: the magic, header layout, unknown flag bits and checksum trailer are
+    /// forwarded as received, and `dest`'s own magic, header layout and strict sequence numbers
+    /// don't apply. Both ends therefore have to speak the same wire format.
+    ///
+    /// All fragments of a fragmented message are forwarded, one at a time, and the header of the
+    /// first one is returned.
+    pub async fn forward_to<W: AsyncWrite + Unpin, C2>(
+        &mut self,
+        dest: &mut TransportWriter<W, C2>,
+    ) -> ProtocolResult<Header> {
+        let (first, wire, _) = self.read_wire().await?;
+        dest.write_wire(&wire).await?;
+
+        let mut last = first;
+        while last.flags().contains(MessageFlags::FRAGMENTED)
+            && !last.flags().contains(MessageFlags::LAST_FRAGMENT)
+        {
+            let (header, wire, _) = self.read_wire().await?;
+
+            if !is_next_fragment(&first, &header) {
+                return Err(ProtocolError::FragmentMismatch {
+                    sequence: first.sequence_number(),
+                    got: header.sequence_number(),
+                });
+            }
+
+            dest.write_wire(&wire).await?;
+            last = header;
+        }
+
+        Ok(first)
+    }
+
+    /// Reads the next frame as it was on the wire, returning its header, its bytes and where the
+    /// payload sits within them.
+    async fn read_wire(&mut self) -> ProtocolResult<(Header, Bytes, Range<usize>)> {
+        let mut magic = [0u8; 4];
+        self.read_exact(&mut magic).await?;
+        check_magic(&magic, &self.params.magic)?;
+
+        let mut header_buf = [0u8; MAX_HEADER_SIZE];
+        let (header, header_len) = self.read_header_raw(&mut header_buf).await?;
+
+        let prefix_len = magic.len() + header_len;
+        let payload_len = self.frame_payload_len(&header)?;
+
+        let mut wire = BytesMut::with_capacity(prefix_len + payload_len);
+        wire.extend_from_slice(&magic);
+        wire.extend_from_slice(&header_buf[..header_len]);
+        wire.resize(prefix_len + payload_len, 0);
+        self.read_exact(&mut wire[prefix_len..]).await?;
+
+        if let Some(trailer) = self.read_checksum(&header, &wire[prefix_len..]).await? {
+            wire.extend_from_slice(&trailer);
+        }
+        self.track_sequence(&header)?;
+
+        Ok((header, wire.freeze(), prefix_len..prefix_len + payload_len))
+    }
+
+    /// Allocates a zeroed payload buffer of `len` bytes through the configured allocator, with its
+    /// start honouring the configured payload alignment.
+    fn alloc_payload(&self, len: usize) -> BytesMut {
+        let align = self.payload_alignment;
+
+        if align <= 1 {
+            let mut buffer = self.allocator.alloc(len);
+            buffer.resize(len, 0);
+
+            return buffer;
+        }
+
+        let mut buffer = self.allocator.alloc(len + align - 1);
+        buffer.resize(len + align - 1, 0);
+
+        let offset = buffer.as_ptr().align_offset(align);
+
+        buffer.advance(offset);
+        buffer.truncate(len);
+
+        buffer
+    }
+
+    /// Length of the payload following `header`, enforcing the configured maximum.
+    #[inline]
+    fn frame_payload_len(&self, header: &Header) -> ProtocolResult<usize> {
+        if !header.flags().contains(MessageFlags::HAS_PAYLOAD) {
+            return Ok(0);
+        }
+
+        let len = header.payload_len();
+        if len > self.max_payload_len {
+            return Err(ProtocolError::PayloadTooLarge {
+                len: len.into(),
+                max: self.max_payload_len,
+            });
+        }
+
+        Ok(len as usize)
+    }
+
+    /// Reads the header and payload of a frame whose magic has already been consumed. If it's the
+    /// first of several fragments, the rest are read and reassembled into one payload.
+    async fn read_frame_after_magic(&mut self) -> ProtocolResult<(Header, BytesMut)> {
+        let (header, payload) = self.read_fragment().await?;
+        self.track_sequence(&header)?;
+
+        let mut payload = payload;
+        let header = self.reassemble(header, &mut payload).await?;
+
+        Ok((header, payload))
+    }
+
+    /// Reads the header and payload of a single frame whose magic has already been consumed.
+    async fn read_fragment(&mut self) -> ProtocolResult<(Header, BytesMut)> {
+        let header = self.read_header().await?;
+
+        let mut payload = self.alloc_payload(self.frame_payload_len(&header)?);
+        self.read_exact(&mut payload).await?;
+        self.read_checksum(&header, &payload).await?;
+
+        Ok((header, payload))
+    }
+
+    /// If `first` is the first of several fragments, reads the ones following it into `payload`
+    /// up to the one flagged [`MessageFlags::LAST_FRAGMENT`], see
+    /// [`TransportWriter::write_stream`](super::TransportWriter::write_stream). Otherwise `first`
+    /// is returned as is.
+    ///
+    /// The returned header is `first`'s without the fragment flags, with the payload length of the
+    /// whole body. The whole body is held to the maximum payload length, like a single frame's
+    /// payload. Payload alignment is only kept for the first fragment.
+    ///
+    /// Fragments flagged [`MessageFlags::COMPRESSED`] carry one zstd stream between them, which
+    /// is kept as it is here; the reassembled body is decompressed as a whole when it's decoded,
+    /// like a single compressed frame. All fragments of a message have to agree on the flag.
+    async fn reassemble(
+        &mut self,
+        first: Header,
+        payload: &mut BytesMut,
+    ) -> ProtocolResult<Header> {
+        if !first.flags().contains(MessageFlags::FRAGMENTED) {
+            return Ok(first);
+        }
+
+        let mut last = first;
+
+        while !last.flags().contains(MessageFlags::LAST_FRAGMENT) {
+            self.read_magic().await?;
+            let header = self.read_header().await?;
+
+            if !is_next_fragment(&first, &header) {
+                return Err(ProtocolError::FragmentMismatch {
+                    sequence: first.sequence_number(),
+                    got: header.sequence_number(),
+                });
+            }
+
+            let start = payload.len();
+            let total = start as u64 + self.frame_payload_len(&header)? as u64;
+            if total > self.max_payload_len as u64 {
+                return Err(ProtocolError::PayloadTooLarge {
+                    len: total,
+                    max: self.max_payload_len,
+                });
+            }
+
+            payload.resize(total as usize, 0);
+            self.read_exact(&mut payload[start..]).await?;
+            self.read_checksum(&header, &payload[start..]).await?;
+
+            last = header;
+        }
+
+        // Can't overflow, the total was checked against the `u32` maximum above.
+        let header = first
+            .with_flags(first.flags() & !(MessageFlags::FRAGMENTED | MessageFlags::LAST_FRAGMENT))
+            .with_payload_len(payload.len() as u32);
+
+        Ok(header)
+    }
+
+    /// Decodes the body of a fully read frame, checking `T` against the type registry if one is
+    /// configured.
+    pub(crate) fn decode_message<T: 'static>(
+        &self,
+        header: &Header,
+        payload: &[u8],
+    ) -> ProtocolResult<T>
+    where
+        C: BodyCodec<T>,
+    {
+        if let Some(registry) = &self.type_registry {
+            registry.check::<T>(header.id())?;
+        }
+
+        self.decode_payload(header, payload)
+    }
+
+    /// Decodes a payload as `T`, decrypting and decompressing it first as `header` says.
+    fn decode_payload<T>(&self, header: &Header, payload: &[u8]) -> ProtocolResult<T>
+    where
+        C: BodyCodec<T>,
+    {
+        let payload = self.open_payload(header, payload)?;
+        if let Some(registry) = &self.type_registry {
+            registry.check_fixed_len(header.id(), &payload)?;
+        }
+
+        let body = self.codec.decode(&payload);
+
+        #[cfg(feature = "tracing")]
+        if let Err(err) = &body {
+            tracing::warn!(error = %err, "failed to decode message body");
+        }
+
+        body
+    }
+
+    /// Undoes encryption and compression of a payload, in that order, borrowing it if neither
+    /// was applied.
+    fn open_payload<'p>(
+        &self,
+        header: &Header,
+        payload: &'p [u8],
+    ) -> ProtocolResult<Cow<'p, [u8]>> {
+        let mut payload = Cow::Borrowed(payload);
+
+        if header.flags().contains(MessageFlags::ENCRYPTED) {
+            payload = Cow::Owned(self.decrypt(header, &payload)?);
+        }
+        if header.flags().contains(MessageFlags::COMPRESSED) {
+            payload = Cow::Owned(self.decompress(&payload)?);
+        }
+
+        Ok(payload)
+    }
+
+    /// Decrypts a payload with the configured cipher.
+    fn decrypt(&self, header: &Header, payload: &[u8]) -> ProtocolResult<Vec<u8>> {
+        #[cfg(feature = "encryption")]
+        {
+            let cipher = self.cipher.as_ref().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "received an encrypted frame without a cipher configured",
+                )
+            })?;
+
+            cipher.open(header, payload)
+        }
+
+        #[cfg(not(feature = "encryption"))]
+        {
+            let _ = (header, payload);
+
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "encrypted frames require the `encryption` feature",
+            )
+            .into())
+        }
+    }
+
+    /// Decompresses a payload, refusing to inflate it past the maximum payload length.
+    fn decompress(&self, payload: &[u8]) -> ProtocolResult<Vec<u8>> {
+        #[cfg(feature = "compression")]
+        {
+            use std::io::Read;
+
+            let max = self.max_payload_len;
+            let mut decompressed = Vec::new();
+
+            zstd::stream::read::Decoder::new(payload)?
+                .take(max as u64 + 1)
+                .read_to_end(&mut decompressed)?;
+
+            if decompressed.len() > max as usize {
+                return Err(ProtocolError::PayloadTooLarge {
+                    len: decompressed.len() as u64,
+                    max,
+                });
+            }
+
+            Ok(decompressed)
+        }
+
+        #[cfg(not(feature = "compression"))]
+        {
+            let _ = payload;
+
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "compressed frames require the `compression` feature",
+            )
+            .into())
+        }
+    }
+
+    async fn read_header(&mut self) -> ProtocolResult<Header> {
+        let mut buf = [0u8; MAX_HEADER_SIZE];
+
+        self.read_header_raw(&mut buf)
+            .await
+            .map(|(header, _)| header)
+    }
+
+    /// Reads a header in the configured layout into `buf`, returning the parsed header and the
+    /// number of bytes it occupied.
+    async fn read_header_raw(
+        &mut self,
+        buf: &mut [u8; MAX_HEADER_SIZE],
+    ) -> ProtocolResult<(Header, usize)> {
+        let len = match self.header_layout {
+            HeaderLayout::Standard | HeaderLayout::LittleEndian => {
+                self.read_exact(&mut buf[..HEADER_SIZE]).await?;
+
+                HEADER_SIZE
+            }
+            HeaderLayout::Varint => {
+                self.read_exact(&mut buf[..=VARINT_PREFIX_SIZE]).await?;
+
+                let len = VARINT_PREFIX_SIZE + varint_len(buf[VARINT_PREFIX_SIZE]);
+                self.read_exact(&mut buf[VARINT_PREFIX_SIZE + 1..len])
+                    .await?;
+
+                len
+            }
+            HeaderLayout::Compact => {
+                self.read_exact(&mut buf[..COMPACT_HEADER_SIZE]).await?;
+
+                COMPACT_HEADER_SIZE
+            }
+        };
+
+        // `buf` keeps the bytes as they were on the wire, the parsers get the crate's own layout.
+        self.last_header = (*buf, len);
+        let mut bytes = *buf;
+        let parsed = match self.header_layout {
+            HeaderLayout::Standard => {
+                bytes[0] = self.params.decode_first_byte(bytes[0])?;
+
+                Header::parse::<StandardHeaderParser>(&bytes[..len])
+            }
+            HeaderLayout::LittleEndian => {
+                bytes[0] = self.params.decode_first_byte(bytes[0])?;
+
+                Header::parse::<LittleEndianHeaderParser>(&bytes[..len])
+            }
+            HeaderLayout::Varint => {
+                bytes[0] = self.params.decode_first_byte(bytes[0])?;
+
+                VarintHeaderParser::parse(&bytes[..len]).map(|(header, _)| header)
+            }
+            HeaderLayout::Compact => CompactHeaderParser::parse(&bytes[..len]),
+        };
+
+        let header = parsed
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Failed to parse header"))?;
+        self.check_version(&header)?;
+
+        Ok((self.infer_payload_len(header), len))
+    }
+
+    /// Fills in the payload length of `header` from the type registry, if it has `HAS_PAYLOAD` set
+    /// but a `payload_len` of zero and its id is registered as fixed-size.
+    fn infer_payload_len(&self, header: Header) -> Header {
+        if !header.flags().contains(MessageFlags::HAS_PAYLOAD) || header.payload_len() != 0 {
+            return header;
+        }
+
+        match self
+            .type_registry
+            .as_ref()
+            .and_then(|registry| registry.fixed_len(header.id()))
+        {
+            Some(len) => header.with_payload_len(len),
+            None => header,
+        }
+    }
+
+    /// Rejects `header` if its version is newer than the supported one, when one is configured.
+    fn check_version(&self, header: &Header) -> ProtocolResult<()> {
+        match self.supported_version {
+            Some(supported) if header.version() > supported => {
+                Err(ProtocolError::UnsupportedVersion {
+                    got: header.version(),
+                    supported,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks the sequence number of a fully read frame against the previous one, when sequence
+    /// tracking is enabled.
+    ///
+    /// A number that doesn't increase is rejected and not remembered, so every later frame is
+    /// still compared against the highest number seen. Wrapping from `u64::MAX` to 0 counts as in
+    /// order.
+    fn track_sequence(&mut self, header: &Header) -> ProtocolResult<()> {
+        if !self.sequence_tracking {
+            return Ok(());
+        }
+
+        let got = header.sequence_number();
+
+        if let Some(last) = self.last_sequence {
+            let expected = last.wrapping_add(1);
+
+            if got <= last && got != expected {
+                return Err(ProtocolError::SequenceRegression {
+                    expected_gt: last,
+                    got,
+                });
+            }
+
+            if got != expected {
+                if let Some(callback) = &mut self.gap_callback {
+                    callback(expected, got);
+                }
+            }
+        }
+
+        self.last_sequence = Some(got);
+
+        Ok(())
+    }
+
+    /// Fills `buf` from the reader, keeping track of the read offset.
+    ///
+    /// With a read timeout configured, fails with [`ProtocolError::Timeout`] if `buf` isn't filled
+    /// in time.
+    async fn read_exact(&mut self, buf: &mut [u8]) -> ProtocolResult<()> {
+        match self.read_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, self.reader.read_exact(buf))
+                .await
+                .map_err(|_| ProtocolError::Timeout)??,
+            None => self.reader.read_exact(buf).await?,
+        };
+        self.read_offset += buf.len() as u64;
+
+        Ok(())
+    }
+
+    /// Like [`TransportReader::read_exact`], but reads whatever is available into `buf` and
+    /// returns how much that was, with `0` meaning the reader has ended.
+    async fn read_some(&mut self, buf: &mut [u8]) -> ProtocolResult<usize> {
+        let read = match self.read_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, self.reader.read(buf))
+                .await
+                .map_err(|_| ProtocolError::Timeout)??,
+            None => self.reader.read(buf).await?,
+        };
+        self.read_offset += read as u64;
+
+        Ok(read)
+    }
+
+    async fn read_magic(&mut self) -> ProtocolResult<()> {
+        let mut magic = [0u8; 4];
+        self.read_exact(&mut magic).await?;
+
+        check_magic(&magic, &self.params.magic)
+    }
+
+    /// Reads the checksum trailer following `payload` if `header` says there is one, and verifies
+    /// it against the header bytes last read and `payload`. Returns the trailer bytes that were
+    /// read.
+    async fn read_checksum(
+        &mut self,
+        header: &Header,
+        payload: &[u8],
+    ) -> ProtocolResult<Option<[u8; 4]>> {
+        if !header.flags().contains(MessageFlags::HAS_CHECKSUM) {
+            return Ok(None);
+        }
+
+        let (header_buf, header_len) = self.last_header;
+        let computed = checksum(&header_buf[..header_len], payload)?;
+
+        let mut trailer = [0u8; 4];
+        self.read_exact(&mut trailer).await?;
+
+        if trailer != computed {
+            return Err(ProtocolError::ChecksumMismatch {
+                expected: u32::from_be_bytes(computed),
+                got: u32::from_be_bytes(trailer),
+            });
+        }
+
+        Ok(Some(trailer))
+    }
+}
+
+/// Whether `header` can follow `first` as a fragment of the same message.
+fn is_next_fragment(first: &Header, header: &Header) -> bool {
+    let compressed = MessageFlags::COMPRESSED;
+
+    header.flags().contains(MessageFlags::FRAGMENTED)
+        && header.flags().contains(compressed) == first.flags().contains(compressed)
+        && header.id() == first.id()
+        && header.sequence_number() == first.sequence_number()
+}
+
+/// Progress of a stream returned by [`TransportReader::read_items`].
+enum ItemsState<'a, R: AsyncRead + Unpin, C> {
+    Unread(&'a mut TransportReader<R, C>),
+    Decoding { payload: Bytes, remaining: u64 },
+    Done,
+}
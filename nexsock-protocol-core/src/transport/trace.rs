@@ -0,0 +1,37 @@
+//! Instrumentation behind the `tracing` feature. Without it, the helpers here compile to nothing
+//! and no logging crate is pulled in.
+
+use crate::header::Header;
+
+/// A span for reading or writing one message, with the header fields left empty until
+/// [`record_header`] fills them in.
+#[cfg(feature = "tracing")]
+macro_rules! message_span {
+    ($name:literal) => {
+        tracing::debug_span!(
+            $name,
+            sequence_number = tracing::field::Empty,
+            payload_len = tracing::field::Empty,
+            flags = tracing::field::Empty,
+        )
+    };
+}
+
+#[cfg(feature = "tracing")]
+pub(super) use message_span;
+
+/// Records `header` on the current message span.
+#[inline]
+pub(super) fn record_header(header: &Header) {
+    #[cfg(feature = "tracing")]
+    {
+        let span = tracing::Span::current();
+
+        span.record("sequence_number", header.sequence_number());
+        span.record("payload_len", header.payload_len());
+        span.record("flags", tracing::field::display(header.flags()));
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    let _ = header;
+}
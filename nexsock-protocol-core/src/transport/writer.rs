@@ -0,0 +1,637 @@
+use super::{HeaderLayout, MAX_HEADER_SIZE, WireParams, checksum, payload_len, put_header, trace};
+use crate::constants::HEADER_SIZE;
+use crate::error::{ProtocolError, ProtocolResult};
+use crate::frame::{Frame, FrameRef};
+use crate::header::Header;
+use crate::header::standard::StandardHeaderParser;
+use crate::message_flags::MessageFlags;
+use crate::traits::body_codec::{BincodeCodec, BodyCodec};
+use bytes::{Bytes, BytesMut};
+use std::io;
+use std::ops::{Deref, DerefMut};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// The write half of a [`Transport`](super::Transport), see
+/// [`Transport::split`](super::Transport::split).
+///
+/// Like the whole transport, dropping it does not flush, and frames held back by write buffering
+/// are lost; call [`TransportWriter::close`] when done. With the `tracing` feature, dropping it
+/// with buffered frames logs a warning.
+pub struct TransportWriter<W: AsyncWrite + Unpin, C = BincodeCodec> {
+    pub(super) writer: W,
+    pub(super) write_offset: u64,
+    pub(super) header_layout: HeaderLayout,
+    pub(super) params: WireParams,
+    pub(super) compression: bool,
+    pub(super) compression_threshold: usize,
+    pub(super) auto_sequence: bool,
+    pub(super) next_sequence: u64,
+    pub(super) strict_sequence: bool,
+    pub(super) allow_retransmits: bool,
+    pub(super) last_written: Option<u64>,
+    pub(super) prefix_buffer: BytesMut,
+    pub(super) write_buffering: bool,
+    pub(super) write_buffer: WriteBuffer,
+    #[cfg(feature = "encryption")]
+    pub(super) cipher: Option<super::cipher::Cipher>,
+    pub(super) codec: C,
+}
+
+impl<W: AsyncWrite + Unpin> TransportWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            write_offset: 0,
+            header_layout: HeaderLayout::Standard,
+            params: WireParams::default(),
+            compression: false,
+            compression_threshold: super::DEFAULT_COMPRESSION_THRESHOLD,
+            auto_sequence: false,
+            next_sequence: 0,
+            strict_sequence: false,
+            allow_retransmits: false,
+            last_written: None,
+            prefix_buffer: BytesMut::with_capacity(4 + MAX_HEADER_SIZE),
+            write_buffering: false,
+            write_buffer: WriteBuffer::default(),
+            #[cfg(feature = "encryption")]
+            cipher: None,
+            codec: BincodeCodec,
+        }
+    }
+}
+
+impl<W: AsyncWrite + Unpin, C> TransportWriter<W, C> {
+    /// Swaps the body codec, keeping the rest of the configuration.
+    pub(super) fn with_codec<C2>(self, codec: C2) -> TransportWriter<W, C2> {
+        TransportWriter {
+            writer: self.writer,
+            write_offset: self.write_offset,
+            header_layout: self.header_layout,
+            params: self.params,
+            compression: self.compression,
+            compression_threshold: self.compression_threshold,
+            auto_sequence: self.auto_sequence,
+            next_sequence: self.next_sequence,
+            strict_sequence: self.strict_sequence,
+            allow_retransmits: self.allow_retransmits,
+            last_written: self.last_written,
+            prefix_buffer: self.prefix_buffer,
+            write_buffering: self.write_buffering,
+            write_buffer: self.write_buffer,
+            #[cfg(feature = "encryption")]
+            cipher: self.cipher,
+            codec,
+        }
+    }
+
+    /// Total number of bytes handed to the writer since construction.
+    #[inline]
+    pub fn write_offset(&self) -> u64 {
+        self.write_offset
+    }
+
+    /// Sequence number the next message will be stamped with when automatic sequence numbers are
+    /// enabled, see [`Transport::with_auto_sequence`](super::Transport::with_auto_sequence).
+    #[inline]
+    pub fn current_sequence(&self) -> u64 {
+        self.next_sequence
+    }
+
+    /// Makes `next` the sequence number of the next automatically stamped message.
+    pub fn reset_sequence(&mut self, next: u64) {
+        self.next_sequence = next;
+    }
+
+    /// Encodes `message` and writes it as a complete frame, then flushes the writer, or holds it
+    /// back until [`TransportWriter::flush`] with write buffering enabled. Returns the header the
+    /// frame was sent with.
+    ///
+    /// The payload length and [`MessageFlags::HAS_PAYLOAD`] in the frame's header are filled in
+    /// from the encoded body, so callers only need to provide the id, version, remaining flags and
+    /// sequence number. With automatic sequence numbers enabled the sequence number is filled in
+    /// as well.
+    pub async fn write_message<T>(
+        &mut self,
+        message: Frame<{ HEADER_SIZE }, T>,
+    ) -> ProtocolResult<Header>
+    where
+        C: BodyCodec<T>,
+    {
+        let write = async {
+            let (header, payload) = self.encode_message(&message)?;
+            trace::record_header(&header);
+
+            self.write_frame(&header, &payload).await?;
+
+            #[cfg(feature = "tracing")]
+            tracing::debug!("message written");
+
+            Ok(header)
+        };
+
+        #[cfg(feature = "tracing")]
+        let write = tracing::Instrument::instrument(write, trace::message_span!("write_message"));
+
+        write.await
+    }
+
+    /// Encodes (and, if enabled, compresses and encrypts) the body of `message`, returning it along
+    /// with the header it should be sent with.
+    ///
+    /// With automatic sequence numbers enabled this takes the next one, even if the frame then
+    /// fails to be written.
+    pub(crate) fn encode_message<T>(
+        &mut self,
+        message: &Frame<{ HEADER_SIZE }, T>,
+    ) -> ProtocolResult<(Header, Vec<u8>)>
+    where
+        C: BodyCodec<T>,
+    {
+        let (header, payload) = self.encode_unstamped(message)?;
+        let header = self.stamp_sequence(header);
+
+        self.encrypt(header, payload)
+    }
+
+    /// Like [`TransportWriter::encode_message`], but leaves the sequence number alone and doesn't
+    /// encrypt, since the header the payload is sealed under includes the sequence number.
+    pub(super) fn encode_unstamped<T>(
+        &self,
+        message: &Frame<{ HEADER_SIZE }, T>,
+    ) -> ProtocolResult<(Header, Vec<u8>)>
+    where
+        C: BodyCodec<T>,
+    {
+        let header = Header::parse::<StandardHeaderParser>(&message.header()).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "Failed to parse frame header")
+        })?;
+
+        let mut payload = self.codec.encode(message.body())?;
+
+        let mut flags = header.flags() & !(MessageFlags::COMPRESSED | MessageFlags::ENCRYPTED);
+        if self.compression && payload.len() >= self.compression_threshold {
+            let compressed = Self::compress(&payload)?;
+
+            if compressed.len() < payload.len() {
+                payload = compressed;
+                flags.insert(MessageFlags::COMPRESSED);
+            }
+        }
+
+        if payload.is_empty() {
+            flags.remove(MessageFlags::HAS_PAYLOAD);
+        } else {
+            flags.insert(MessageFlags::HAS_PAYLOAD);
+        }
+
+        let header = Header::new(
+            header.id(),
+            header.version(),
+            flags,
+            payload_len(payload.len())?,
+            header.sequence_number(),
+        );
+
+        Ok((header, payload))
+    }
+
+    /// Replaces the sequence number of `header` with the next one from the counter, if automatic
+    /// sequence numbers are enabled.
+    pub(super) fn stamp_sequence(&mut self, header: Header) -> Header {
+        if !self.auto_sequence {
+            return header;
+        }
+
+        let sequence_number = self.next_sequence;
+        self.next_sequence = sequence_number.wrapping_add(1);
+
+        header.with_sequence_number(sequence_number)
+    }
+
+    /// Encrypts `payload` and flags `header` as [`MessageFlags::ENCRYPTED`], if a cipher is
+    /// configured. Must come after the sequence number is final.
+    pub(super) fn encrypt(
+        &self,
+        header: Header,
+        payload: Vec<u8>,
+    ) -> ProtocolResult<(Header, Vec<u8>)> {
+        #[cfg(feature = "encryption")]
+        if let Some(cipher) = &self.cipher {
+            use super::cipher::{NONCE_SIZE, TAG_SIZE};
+
+            let header = Header::new(
+                header.id(),
+                header.version(),
+                header.flags() | MessageFlags::ENCRYPTED | MessageFlags::HAS_PAYLOAD,
+                payload_len(NONCE_SIZE + payload.len() + TAG_SIZE)?,
+                header.sequence_number(),
+            );
+            let sealed = cipher.seal(&header, &payload)?;
+
+            return Ok((header, sealed));
+        }
+
+        Ok((header, payload))
+    }
+
+    /// Writes a complete frame in the configured header layout and flushes the writer, unless
+    /// write buffering is enabled.
+    ///
+    /// Frames flagged with [`MessageFlags::HAS_CHECKSUM`] get a CRC32 trailer over the header and
+    /// payload, which requires the `checksum` feature.
+    pub(crate) async fn write_frame(
+        &mut self,
+        header: &Header,
+        payload: &[u8],
+    ) -> ProtocolResult<()> {
+        self.check_sequence(header.sequence_number())?;
+
+        self.write_frame_unchecked(header, payload).await
+    }
+
+    /// Reads `total_len` bytes of an already encoded body from `source` and writes them as
+    /// fragments of at most `chunk_size` bytes, flagged [`MessageFlags::FRAGMENTED`] and sharing
+    /// one sequence number. The last one is also flagged [`MessageFlags::LAST_FRAGMENT`]. Readers
+    /// reassemble them into a single body, which has to fit their maximum payload length.
+    ///
+    /// Only one chunk is held in memory at a time, unless write buffering is enabled, in which case
+    /// every fragment stays in the write buffer until [`TransportWriter::flush`].
+    ///
+    /// With compression enabled and `total_len` at least the compression threshold, the body is
+    /// compressed as a single zstd stream spanning all fragments, flushed at the end of each, so
+    /// later chunks can refer back to earlier ones. Every fragment is then flagged
+    /// [`MessageFlags::COMPRESSED`], and readers decompress the reassembled body as a whole. Unlike
+    /// [`TransportWriter::write_message`], the stream is compressed even if it doesn't shrink,
+    /// since that's only known once it's been sent.
+    ///
+    /// `header` provides the id, version, remaining flags and sequence number, which is filled in
+    /// if automatic sequence numbers are enabled. The fragments aren't encrypted, since readers
+    /// only decrypt whole frames. Returns the header of the last fragment.
+    pub async fn write_stream<S: AsyncRead + Unpin>(
+        &mut self,
+        header: Header,
+        mut source: S,
+        total_len: u64,
+        chunk_size: usize,
+    ) -> ProtocolResult<Header> {
+        if chunk_size == 0 || chunk_size > u32::MAX as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "chunk size must be between 1 and u32::MAX bytes",
+            )
+            .into());
+        }
+
+        let header = self.stamp_sequence(header);
+        self.check_sequence(header.sequence_number())?;
+
+        let mut flags = (header.flags()
+            & !(MessageFlags::COMPRESSED | MessageFlags::ENCRYPTED | MessageFlags::LAST_FRAGMENT))
+            | MessageFlags::FRAGMENTED;
+
+        let mut compressor = None;
+        if self.compression && total_len >= self.compression_threshold as u64 {
+            compressor = Some(StreamCompressor::new()?);
+            flags.insert(MessageFlags::COMPRESSED);
+        }
+
+        let mut chunk = vec![0u8; (chunk_size as u64).min(total_len) as usize];
+        let mut remaining = total_len;
+
+        loop {
+            let len = (chunk_size as u64).min(remaining) as usize;
+            remaining -= len as u64;
+
+            let chunk = &mut chunk[..len];
+            source.read_exact(chunk).await?;
+
+            let compressed;
+            let payload: &[u8] = match &mut compressor {
+                Some(compressor) => {
+                    compressed = compressor.compress(chunk, remaining == 0)?;
+                    &compressed
+                }
+                None => chunk,
+            };
+
+            let mut flags = flags;
+            if !payload.is_empty() {
+                flags.insert(MessageFlags::HAS_PAYLOAD);
+            } else {
+                flags.remove(MessageFlags::HAS_PAYLOAD);
+            }
+            if remaining == 0 {
+                flags.insert(MessageFlags::LAST_FRAGMENT);
+            }
+
+            let fragment = header
+                .with_flags(flags)
+                .with_payload_len(payload_len(payload.len())?);
+            self.write_frame_unchecked(&fragment, payload).await?;
+
+            if remaining == 0 {
+                return Ok(fragment);
+            }
+        }
+    }
+
+    /// [`TransportWriter::write_frame`] without the strict sequence check, which fragments after
+    /// the first would fail.
+    async fn write_frame_unchecked(
+        &mut self,
+        header: &Header,
+        payload: &[u8],
+    ) -> ProtocolResult<()> {
+        // Magic and header go out in one write from a buffer that's reused across frames.
+        let mut prefix = std::mem::take(&mut self.prefix_buffer);
+        prefix.clear();
+        prefix.extend_from_slice(&self.params.magic);
+        put_header(header, self.header_layout, self.params, &mut prefix)?;
+
+        let trailer = if header.flags().contains(MessageFlags::HAS_CHECKSUM) {
+            Some(checksum(&prefix[self.params.magic.len()..], payload)?)
+        } else {
+            None
+        };
+
+        let written = self.write_all(&prefix).await;
+        self.prefix_buffer = prefix;
+        written?;
+
+        self.write_all(payload).await?;
+        if let Some(trailer) = trailer {
+            self.write_all(&trailer).await?;
+        }
+
+        self.end_frame(Some(header)).await
+    }
+
+    /// Writes a frame whose body is already encoded, e.g. one built with
+    /// [`Frame::from_encoded`] or received through
+    /// [`TransportReader::read_raw`](super::TransportReader::read_raw).
+    ///
+    /// The header is sent in the configured layout and its `payload_len` has to match the body.
+    /// If it has [`MessageFlags::HAS_CHECKSUM`] set, the checksum trailer is computed and
+    /// appended, since the body doesn't include it. Like [`TransportWriter::write_message`], this
+    /// flushes unless write buffering is enabled.
+    pub async fn write_raw(&mut self, frame: &Frame<{ HEADER_SIZE }, Bytes>) -> ProtocolResult<()> {
+        self.write_raw_parts(frame.header(), frame.body()).await
+    }
+
+    /// Like [`TransportWriter::write_raw`], for a frame borrowed with
+    /// [`TransportReader::read_frame_raw`](super::TransportReader::read_frame_raw).
+    pub async fn write_frame_ref(
+        &mut self,
+        frame: &FrameRef<'_, { HEADER_SIZE }>,
+    ) -> ProtocolResult<()> {
+        self.write_raw_parts(frame.header(), frame.body()).await
+    }
+
+    /// Writes `wire` exactly as given and flushes the writer, unless write buffering is enabled.
+    /// It has to hold complete frames,
+    /// magic included, e.g. as returned by
+    /// [`TransportReader::read_frame_with_wire`](super::TransportReader::read_frame_with_wire).
+    ///
+    /// The frames aren't parsed, so with write buffering a [`MessageFlags::REQUIRES_ACK`] frame
+    /// written this way still waits for [`TransportWriter::flush`].
+    pub async fn write_wire(&mut self, wire: &[u8]) -> ProtocolResult<()> {
+        self.write_all(wire).await?;
+
+        self.end_frame(None).await
+    }
+
+    /// Writes the magic bytes a frame starts with, as configured with
+    /// [`Transport::with_params`](super::Transport::with_params), without flushing. The rest of
+    /// the frame has to follow, e.g. through [`TransportWriter::write_wire`].
+    pub async fn write_magic(&mut self) -> ProtocolResult<()> {
+        let magic = self.params.magic;
+
+        self.write_all(&magic).await
+    }
+
+    /// Writes an already encoded body under `header`, given in the standard layout, re-encoding
+    /// the header in the configured layout.
+    async fn write_raw_parts(
+        &mut self,
+        header: [u8; HEADER_SIZE],
+        body: &[u8],
+    ) -> ProtocolResult<()> {
+        let header = Header::parse::<StandardHeaderParser>(&header).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "Failed to parse frame header")
+        })?;
+
+        if header.payload_len() as usize != body.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "header declares a {} byte payload, but the body is {} bytes",
+                    header.payload_len(),
+                    body.len()
+                ),
+            )
+            .into());
+        }
+
+        self.write_frame(&header, body).await
+    }
+
+    /// Checks the sequence number of a frame about to be written against the previous one, when
+    /// strict sequence numbers are enabled.
+    ///
+    /// Like sequence tracking on the read side, wrapping from `u64::MAX` to 0 counts as in order,
+    /// and a rejected number is not remembered.
+    fn check_sequence(&mut self, got: u64) -> ProtocolResult<()> {
+        if !self.strict_sequence {
+            return Ok(());
+        }
+
+        if let Some(last) = self.last_written {
+            let in_order = got > last
+                || got == last.wrapping_add(1)
+                || (self.allow_retransmits && got == last);
+
+            if !in_order {
+                return Err(ProtocolError::SequenceRegression {
+                    expected_gt: last,
+                    got,
+                });
+            }
+        }
+
+        self.last_written = Some(got);
+
+        Ok(())
+    }
+
+    /// Compresses a payload with zstd at the default level.
+    fn compress(payload: &[u8]) -> ProtocolResult<Vec<u8>> {
+        #[cfg(feature = "compression")]
+        {
+            Ok(zstd::bulk::compress(payload, 0)?)
+        }
+
+        #[cfg(not(feature = "compression"))]
+        {
+            let _ = payload;
+
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "compression requires the `compression` feature",
+            )
+            .into())
+        }
+    }
+
+    /// Writes all of `buf`, keeping track of the write offset.
+    ///
+    /// With write buffering enabled, `buf` is only appended to the write buffer. Otherwise frames
+    /// left in the buffer from before buffering was disabled are written first, so they still go
+    /// out in order.
+    async fn write_all(&mut self, buf: &[u8]) -> ProtocolResult<()> {
+        if self.write_buffering {
+            self.write_buffer.extend_from_slice(buf);
+        } else {
+            self.write_buffered().await?;
+            self.writer.write_all(buf).await?;
+        }
+        self.write_offset += buf.len() as u64;
+
+        Ok(())
+    }
+
+    /// Flushes the writer after a complete frame, unless write buffering holds frames back for
+    /// [`TransportWriter::flush`].
+    ///
+    /// A frame flagged [`MessageFlags::REQUIRES_ACK`] flushes the write buffer along with it, so
+    /// the peer's ack isn't held up behind frames that are waiting for a flush.
+    async fn end_frame(&mut self, header: Option<&Header>) -> ProtocolResult<()> {
+        let requires_ack =
+            header.is_some_and(|header| header.flags().contains(MessageFlags::REQUIRES_ACK));
+
+        if !self.write_buffering || requires_ack {
+            self.flush().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Sends everything held in the write buffer in a single write and flushes the writer.
+    ///
+    /// If the write fails the buffered frames are dropped, since it's unknown how much of them
+    /// reached the peer.
+    pub async fn flush(&mut self) -> ProtocolResult<()> {
+        self.write_buffered().await?;
+        self.writer.flush().await?;
+
+        Ok(())
+    }
+
+    /// Writes out the write buffer, if anything is in it, without flushing.
+    async fn write_buffered(&mut self) -> ProtocolResult<()> {
+        if self.write_buffer.is_empty() {
+            return Ok(());
+        }
+
+        let mut buffer = std::mem::take(&mut self.write_buffer);
+        let written = self.writer.write_all(&buffer).await;
+
+        buffer.clear();
+        self.write_buffer = buffer;
+        written?;
+
+        Ok(())
+    }
+
+    /// Flushes any pending writes, including the write buffer, and shuts down the write half.
+    pub async fn close(&mut self) -> ProtocolResult<()> {
+        self.flush().await?;
+        self.writer.shutdown().await?;
+
+        Ok(())
+    }
+}
+
+/// zstd stream spanning the fragments of one [`TransportWriter::write_stream`] call.
+struct StreamCompressor {
+    #[cfg(feature = "compression")]
+    encoder: zstd::stream::write::Encoder<'static, Vec<u8>>,
+}
+
+impl StreamCompressor {
+    fn new() -> ProtocolResult<Self> {
+        #[cfg(feature = "compression")]
+        {
+            Ok(Self {
+                encoder: zstd::stream::write::Encoder::new(Vec::new(), 0)?,
+            })
+        }
+
+        #[cfg(not(feature = "compression"))]
+        {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "compression requires the `compression` feature",
+            )
+            .into())
+        }
+    }
+
+    /// Compresses the next chunk, returning everything the stream has produced for it. The
+    /// output is flushed, so a reader can decompress up to the end of it, and the stream is
+    /// ended after the `last` chunk.
+    fn compress(&mut self, chunk: &[u8], last: bool) -> ProtocolResult<Vec<u8>> {
+        #[cfg(feature = "compression")]
+        {
+            use std::io::Write;
+
+            self.encoder.write_all(chunk)?;
+            if last {
+                self.encoder.do_finish()?;
+            } else {
+                self.encoder.flush()?;
+            }
+
+            Ok(std::mem::take(self.encoder.get_mut()))
+        }
+
+        #[cfg(not(feature = "compression"))]
+        {
+            let _ = (chunk, last);
+
+            unreachable!("a stream compressor can't be created without the `compression` feature")
+        }
+    }
+}
+
+/// Frames held back by write buffering. Lives in its own type so that dropping a writer with
+/// frames still in it can be reported without a `Drop` impl on [`TransportWriter`], which would
+/// stop its fields from being moved out when swapping the codec.
+#[derive(Debug, Default)]
+pub(super) struct WriteBuffer(BytesMut);
+
+impl Deref for WriteBuffer {
+    type Target = BytesMut;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for WriteBuffer {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl Drop for WriteBuffer {
+    fn drop(&mut self) {
+        #[cfg(feature = "tracing")]
+        if !self.0.is_empty() {
+            tracing::warn!(
+                len = self.0.len(),
+                "transport writer dropped with buffered frames that were never flushed"
+            );
+        }
+    }
+}
@@ -0,0 +1,32 @@
+//! Header round trip without `std`, run with `cargo test --no-default-features --test no_std`.
+//! The test harness itself still needs `std`, but this crate and the library don't use it.
+
+#![no_std]
+
+use nexsock_protocol_core::constants::HEADER_SIZE;
+use nexsock_protocol_core::frame::Frame;
+use nexsock_protocol_core::header::Header;
+use nexsock_protocol_core::header::optimized::OptimizedHeaderParser;
+use nexsock_protocol_core::header::standard::StandardHeaderParser;
+use nexsock_protocol_core::message_flags::MessageFlags;
+use nexsock_protocol_core::traits::MessageBody;
+
+#[test]
+fn header_roundtrip() {
+    let header = Header::new(
+        17,
+        2,
+        MessageFlags::HAS_PAYLOAD | MessageFlags::REQUIRES_ACK,
+        0x0102_0304,
+        u64::MAX,
+    );
+
+    let bytes = header.to_bytes::<StandardHeaderParser>();
+    assert_eq!(bytes.len(), HEADER_SIZE);
+    assert_eq!(Header::parse::<StandardHeaderParser>(&bytes), Some(header));
+    assert_eq!(Header::parse::<OptimizedHeaderParser>(&bytes), Some(header));
+    assert_eq!(Header::parse::<StandardHeaderParser>(&bytes[1..]), None);
+
+    let frame: Frame<HEADER_SIZE, ()> = ().to_frame(bytes);
+    assert_eq!(frame.header(), bytes);
+}
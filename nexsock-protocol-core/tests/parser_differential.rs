@@ -0,0 +1,105 @@
+//! Differential test across the header parser implementations.
+//!
+//! [`StandardHeaderParser`] is the reference: it spells the layout out field by field, and every
+//! other parser has to serialize to its bytes and parse them back to the same header. Random
+//! headers cover the full range of every field, including unknown flag bits.
+
+use nexsock_protocol_core::constants::HEADER_SIZE;
+use nexsock_protocol_core::header::Header;
+use nexsock_protocol_core::header::little_endian::LittleEndianHeaderParser;
+use nexsock_protocol_core::header::optimized::OptimizedHeaderParser;
+use nexsock_protocol_core::header::runtime::RuntimeHeaderParser;
+use nexsock_protocol_core::header::standard::StandardHeaderParser;
+use nexsock_protocol_core::header::varint::{MAX_VARINT_HEADER_SIZE, VarintHeaderParser};
+use nexsock_protocol_core::message_flags::MessageFlags;
+use nexsock_protocol_core::traits::header::{HeaderDeserializer, HeaderSerializer};
+use proptest::prelude::*;
+
+type Serialize = fn(&Header) -> [u8; HEADER_SIZE];
+type Parse = fn(&[u8]) -> Option<Header>;
+
+fn header() -> impl Strategy<Value = Header> {
+    (0..=63u8, 0..=3u8, any::<u16>(), any::<u32>(), any::<u64>()).prop_map(
+        |(id, version, flags, payload_len, sequence_number)| {
+            Header::new(
+                id,
+                version,
+                MessageFlags::from(flags),
+                payload_len,
+                sequence_number,
+            )
+        },
+    )
+}
+
+/// Serializers producing the standard layout, by name.
+fn serializers() -> Vec<(&'static str, Serialize)> {
+    // Only pushed to when a SIMD parser is built.
+    #[cfg_attr(not(feature = "simd"), allow(unused_mut))]
+    let mut serializers: Vec<(&'static str, Serialize)> = vec![
+        ("standard", StandardHeaderParser::serialize),
+        ("runtime", RuntimeHeaderParser::serialize),
+    ];
+
+    #[cfg(all(feature = "simd", target_arch = "x86_64", target_feature = "sse2"))]
+    serializers.push((
+        "sse2",
+        nexsock_protocol_core::header::simd::X86SimdHeaderParser::serialize,
+    ));
+    #[cfg(all(feature = "simd", target_arch = "aarch64", target_feature = "neon"))]
+    serializers.push((
+        "neon",
+        nexsock_protocol_core::header::simd::Aarch64NeonHeaderParser::serialize,
+    ));
+
+    serializers
+}
+
+/// Deserializers reading the standard layout, by name.
+fn deserializers() -> Vec<(&'static str, Parse)> {
+    // Only pushed to when a SIMD parser is built.
+    #[cfg_attr(not(feature = "simd"), allow(unused_mut))]
+    let mut deserializers: Vec<(&'static str, Parse)> = vec![
+        ("standard", StandardHeaderParser::parse),
+        ("optimized", OptimizedHeaderParser::parse),
+        ("runtime", RuntimeHeaderParser::parse),
+    ];
+
+    #[cfg(all(feature = "simd", target_arch = "x86_64", target_feature = "sse2"))]
+    deserializers.push((
+        "sse2",
+        nexsock_protocol_core::header::simd::X86SimdHeaderParser::parse,
+    ));
+    #[cfg(all(feature = "simd", target_arch = "aarch64", target_feature = "neon"))]
+    deserializers.push((
+        "neon",
+        nexsock_protocol_core::header::simd::Aarch64NeonHeaderParser::parse,
+    ));
+
+    deserializers
+}
+
+proptest! {
+    #[test]
+    fn parsers_agree(header in header()) {
+        let reference = StandardHeaderParser::serialize(&header);
+
+        for (name, serialize) in serializers() {
+            prop_assert_eq!(serialize(&header), reference, "{} serializer", name);
+        }
+
+        for (name, parse) in deserializers() {
+            prop_assert_eq!(parse(&reference), Some(header), "{} deserializer", name);
+        }
+    }
+
+    #[test]
+    fn other_layouts_roundtrip(header in header()) {
+        let bytes = LittleEndianHeaderParser::serialize(&header);
+        prop_assert_eq!(LittleEndianHeaderParser::parse(&bytes), Some(header));
+
+        let mut buf = [0u8; MAX_VARINT_HEADER_SIZE];
+        let len = VarintHeaderParser::serialize(&header, &mut buf);
+        prop_assert_eq!(VarintHeaderParser::parse(&buf[..len]), Some((header, len)));
+    }
+}
@@ -0,0 +1,85 @@
+use bincode::{Decode, Encode};
+use nexsock_protocol_core::constants::HEADER_SIZE;
+use nexsock_protocol_core::header::Header;
+use nexsock_protocol_core::header::standard::StandardHeaderParser;
+use nexsock_protocol_core::message_flags::MessageFlags;
+use nexsock_protocol_core::traits::MessageBody;
+use nexsock_protocol_core::transport::Transport;
+use std::sync::Arc;
+use tokio_rustls::rustls::pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer, ServerName};
+use tokio_rustls::rustls::{ClientConfig, RootCertStore, ServerConfig};
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+#[derive(Debug, PartialEq, Encode, Decode)]
+struct Ping {
+    id: u32,
+    note: String,
+}
+
+impl MessageBody for Ping {}
+
+fn header(sequence_number: u64) -> [u8; HEADER_SIZE] {
+    Header::new(1, 1, MessageFlags::NONE, 0, sequence_number).to_bytes::<StandardHeaderParser>()
+}
+
+#[tokio::test]
+async fn test_transport_over_tls() {
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(cert.key_pair.serialize_der()));
+
+    let server_config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(vec![cert.cert.der().clone()], key)
+        .unwrap();
+
+    let mut roots = RootCertStore::empty();
+    roots.add(cert.cert.der().clone()).unwrap();
+    let client_config = ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+    let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+    let connector = TlsConnector::from(Arc::new(client_config));
+    let acceptor = TlsAcceptor::from(Arc::new(server_config));
+
+    let (client, server) = tokio::join!(
+        connector.connect(ServerName::try_from("localhost").unwrap(), client_io),
+        acceptor.accept(server_io),
+    );
+
+    let mut client = Transport::over_tls(client.unwrap());
+    let mut server = Transport::over_tls(server.unwrap());
+
+    let ping = Ping {
+        id: 7,
+        note: "over tls".to_string(),
+    };
+    client
+        .write_message(
+            Ping {
+                id: 7,
+                note: "over tls".to_string(),
+            }
+            .to_frame(header(1)),
+        )
+        .await
+        .unwrap();
+
+    let received: Ping = server.read_message().await.unwrap();
+    assert_eq!(received, ping);
+
+    server
+        .write_message(
+            Ping {
+                id: 8,
+                note: "pong".to_string(),
+            }
+            .to_frame(header(2)),
+        )
+        .await
+        .unwrap();
+
+    let reply: Ping = client.read_message().await.unwrap();
+    assert_eq!(reply.id, 8);
+    assert_eq!(reply.note, "pong");
+}
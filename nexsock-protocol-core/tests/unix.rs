@@ -0,0 +1,50 @@
+#![cfg(unix)]
+
+use bincode::{Decode, Encode};
+use nexsock_protocol_core::header::Header;
+use nexsock_protocol_core::header::standard::StandardHeaderParser;
+use nexsock_protocol_core::message_flags::MessageFlags;
+use nexsock_protocol_core::traits::MessageBody;
+use nexsock_protocol_core::transport::Transport;
+use tokio::net::UnixListener;
+
+#[derive(Debug, PartialEq, Encode, Decode)]
+struct Ping {
+    id: u32,
+    note: String,
+}
+
+impl MessageBody for Ping {}
+
+#[tokio::test]
+async fn test_transport_over_unix_socket() {
+    let path = std::env::temp_dir().join(format!("nexsock-test-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let listener = UnixListener::bind(&path).unwrap();
+    let (client, accepted) = tokio::join!(Transport::connect_unix(&path), listener.accept());
+
+    let mut client = client.unwrap();
+    let mut server = Transport::from_unix_stream(accepted.unwrap().0);
+
+    let ping = Ping {
+        id: 7,
+        note: "over a unix socket".to_string(),
+    };
+    let header = Header::new(1, 1, MessageFlags::NONE, 0, 1).to_bytes::<StandardHeaderParser>();
+    client
+        .write_message(
+            Ping {
+                id: 7,
+                note: "over a unix socket".to_string(),
+            }
+            .to_frame(header),
+        )
+        .await
+        .unwrap();
+
+    let received: Ping = server.read_message().await.unwrap();
+    assert_eq!(received, ping);
+
+    std::fs::remove_file(&path).unwrap();
+}
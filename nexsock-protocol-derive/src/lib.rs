@@ -0,0 +1,267 @@
+//! `#[derive(MessageBody)]` for `nexsock-protocol-core`, enabled there with the `derive` feature
+//! and re-exported as `nexsock_protocol_core::traits::MessageBody`.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::parse::Parser;
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::{
+    Data, DeriveInput, Fields, Generics, Ident, LitInt, Token, TypeParamBound, parse_macro_input,
+    parse_quote,
+};
+
+/// Derives bincode's `Encode`, `Decode` and `BorrowDecode` together with `MessageBody`.
+///
+/// The encoding is the same as bincode's own derives produce: fields in declaration order, and
+/// for enums the variant index as a `u32` ahead of the variant's fields. Don't derive `Encode` or
+/// `Decode` alongside this.
+///
+/// The message id can be set with `#[message(id = 3)]`, which becomes `MessageBody::ID`. It has to
+/// fit in the 6 bits a header has for it, i.e. be at most 63.
+///
+/// ```ignore
+/// use nexsock_protocol_core::traits::MessageBody;
+///
+/// #[derive(MessageBody)]
+/// #[message(id = 3)]
+/// struct Ping {
+///     nonce: u64,
+/// }
+/// ```
+#[proc_macro_derive(MessageBody, attributes(message))]
+pub fn derive_message_body(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let id = message_id(&input)?;
+
+    let (encode_body, decode_body) = match &input.data {
+        Data::Struct(data) => {
+            let (pattern, encode) = encode_fields(&data.fields);
+            let decode = decode_fields(&data.fields);
+
+            (
+                quote! {
+                    let Self #pattern = self;
+                    #encode
+                },
+                quote! { Ok(Self #decode) },
+            )
+        }
+        Data::Enum(data) => {
+            let name = input.ident.to_string();
+            let max = data.variants.len().saturating_sub(1) as u32;
+
+            let mut encode_arms = Vec::new();
+            let mut decode_arms = Vec::new();
+
+            for (index, variant) in data.variants.iter().enumerate() {
+                let index = index as u32;
+                let ident = &variant.ident;
+                let (pattern, encode) = encode_fields(&variant.fields);
+                let decode = decode_fields(&variant.fields);
+
+                encode_arms.push(quote! {
+                    Self::#ident #pattern => {
+                        __bincode::Encode::encode(&#index, encoder)?;
+                        #encode
+                    }
+                });
+                decode_arms.push(quote! { #index => Ok(Self::#ident #decode), });
+            }
+
+            (
+                quote! {
+                    match self {
+                        #(#encode_arms)*
+                    }
+                },
+                quote! {
+                    match <u32 as __bincode::Decode<__Context>>::decode(decoder)? {
+                        #(#decode_arms)*
+                        found => Err(__bincode::error::DecodeError::UnexpectedVariant {
+                            type_name: #name,
+                            allowed: &__bincode::error::AllowedEnumVariants::Range {
+                                min: 0,
+                                max: #max,
+                            },
+                            found,
+                        }),
+                    }
+                },
+            )
+        }
+        Data::Union(data) => {
+            return Err(syn::Error::new(
+                data.union_token.span(),
+                "MessageBody can't be derived for unions",
+            ));
+        }
+    };
+
+    let ident = &input.ident;
+
+    let encode_generics = with_bound(&input.generics, quote!(__bincode::Encode));
+    let (impl_generics, ty_generics, where_clause) = encode_generics.split_for_impl();
+    let encode_impl = quote! {
+        impl #impl_generics __bincode::Encode for #ident #ty_generics #where_clause {
+            fn encode<__E: __bincode::enc::Encoder>(
+                &self,
+                encoder: &mut __E,
+            ) -> ::core::result::Result<(), __bincode::error::EncodeError> {
+                #encode_body
+            }
+        }
+    };
+
+    let mut decode_generics = with_bound(&input.generics, quote!(__bincode::Decode<__Context>));
+    decode_generics.params.push(parse_quote!(__Context));
+    let (impl_generics, _, where_clause) = decode_generics.split_for_impl();
+    let decode_impl = quote! {
+        impl #impl_generics __bincode::Decode<__Context> for #ident #ty_generics #where_clause {
+            fn decode<__D: __bincode::de::Decoder<Context = __Context>>(
+                decoder: &mut __D,
+            ) -> ::core::result::Result<Self, __bincode::error::DecodeError> {
+                #decode_body
+            }
+        }
+    };
+
+    let mut borrow_generics = with_bound(&input.generics, quote!(__bincode::Decode<__Context>));
+    borrow_generics.params.insert(0, parse_quote!('__de));
+    borrow_generics.params.push(parse_quote!(__Context));
+    let (impl_generics, _, where_clause) = borrow_generics.split_for_impl();
+    let borrow_decode_impl = quote! {
+        impl #impl_generics __bincode::BorrowDecode<'__de, __Context> for #ident #ty_generics #where_clause {
+            fn borrow_decode<__D: __bincode::de::BorrowDecoder<'__de, Context = __Context>>(
+                decoder: &mut __D,
+            ) -> ::core::result::Result<Self, __bincode::error::DecodeError> {
+                <Self as __bincode::Decode<__Context>>::decode(decoder)
+            }
+        }
+    };
+
+    let body_generics = with_bound(
+        &input.generics,
+        quote!(__bincode::Encode + __bincode::Decode<()>),
+    );
+    let (impl_generics, _, where_clause) = body_generics.split_for_impl();
+    let id = id.map_or_else(|| quote!(None), |id| quote!(Some(#id)));
+    let body_impl = quote! {
+        impl #impl_generics ::nexsock_protocol_core::traits::MessageBody for #ident #ty_generics #where_clause {
+            const ID: Option<u8> = #id;
+        }
+    };
+
+    Ok(quote! {
+        const _: () = {
+            use ::nexsock_protocol_core::__private::bincode as __bincode;
+
+            #encode_impl
+            #decode_impl
+            #borrow_decode_impl
+            #body_impl
+        };
+    })
+}
+
+/// Largest id that fits in a header, whose id field is `nexsock_protocol_core`'s `ID_BITS` (6)
+/// bits wide.
+const MAX_ID: u8 = (1 << 6) - 1;
+
+/// Reads the id from `#[message(id = ...)]`, if given.
+fn message_id(input: &DeriveInput) -> syn::Result<Option<u8>> {
+    let mut id = None;
+
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("message"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if !meta.path.is_ident("id") {
+                return Err(meta.error("unknown message attribute, expected `id`"));
+            }
+
+            if id.is_some() {
+                return Err(meta.error("duplicate message id"));
+            }
+
+            let lit: LitInt = meta.value()?.parse()?;
+            let value = lit.base10_parse::<u8>()?;
+            if value > MAX_ID {
+                return Err(syn::Error::new(
+                    lit.span(),
+                    format!("message id must be at most {MAX_ID}, header ids are 6 bits wide"),
+                ));
+            }
+
+            id = Some(value);
+
+            Ok(())
+        })?;
+    }
+
+    Ok(id)
+}
+
+/// Binding pattern for `fields` and the statements encoding them in order.
+fn encode_fields(fields: &Fields) -> (TokenStream2, TokenStream2) {
+    let bindings: Vec<Ident> = (0..fields.len())
+        .map(|index| format_ident!("__field{}", index))
+        .collect();
+
+    let pattern = match fields {
+        Fields::Named(named) => {
+            let names = named.named.iter().map(|field| &field.ident);
+            quote!({ #(#names: #bindings),* })
+        }
+        Fields::Unnamed(_) => quote!(( #(#bindings),* )),
+        Fields::Unit => quote!(),
+    };
+
+    let encode = quote! {
+        #(__bincode::Encode::encode(#bindings, encoder)?;)*
+        Ok(())
+    };
+
+    (pattern, encode)
+}
+
+/// Constructor for `fields`, decoding each in order.
+fn decode_fields(fields: &Fields) -> TokenStream2 {
+    let decode = quote!(__bincode::Decode::decode(decoder)?);
+
+    match fields {
+        Fields::Named(named) => {
+            let names = named.named.iter().map(|field| &field.ident);
+            quote!({ #(#names: #decode),* })
+        }
+        Fields::Unnamed(unnamed) => {
+            let values = unnamed.unnamed.iter().map(|_| &decode);
+            quote!(( #(#values),* ))
+        }
+        Fields::Unit => quote!(),
+    }
+}
+
+/// `generics` with `bounds` added to every type parameter.
+fn with_bound(generics: &Generics, bounds: TokenStream2) -> Generics {
+    let bounds = Punctuated::<TypeParamBound, Token![+]>::parse_separated_nonempty
+        .parse2(bounds)
+        .expect("bounds are generated by this crate");
+    let mut generics = generics.clone();
+
+    for param in generics.type_params_mut() {
+        param.bounds.extend(bounds.iter().cloned());
+    }
+
+    generics
+}
@@ -0,0 +1,124 @@
+use nexsock_protocol_core::header::Header;
+use nexsock_protocol_core::header::standard::StandardHeaderParser;
+use nexsock_protocol_core::message_flags::MessageFlags;
+use nexsock_protocol_core::traits::MessageBody;
+use nexsock_protocol_core::transport::Transport;
+
+#[derive(Debug, PartialEq, MessageBody)]
+#[message(id = 3)]
+struct Ping {
+    nonce: u64,
+    note: String,
+}
+
+#[derive(Debug, PartialEq, MessageBody)]
+struct Pair<T>(T, T);
+
+#[derive(Debug, PartialEq, MessageBody)]
+enum Command {
+    Stop,
+    Start { delay: u32 },
+    Rename(String),
+}
+
+/// Mirrors the types above with bincode's own derives, to check the encodings agree.
+mod reference {
+    use bincode::{Decode, Encode};
+
+    #[derive(Encode, Decode)]
+    pub struct Ping {
+        pub nonce: u64,
+        pub note: String,
+    }
+
+    #[derive(Encode, Decode)]
+    pub enum Command {
+        Stop,
+        Start { delay: u32 },
+        Rename(String),
+    }
+}
+
+fn encode<T: bincode::Encode>(value: &T) -> Vec<u8> {
+    bincode::encode_to_vec(value, bincode::config::standard().with_big_endian()).unwrap()
+}
+
+#[test]
+fn test_id() {
+    assert_eq!(Ping::ID, Some(3));
+    assert_eq!(Command::ID, None);
+    assert_eq!(<Pair<u8>>::ID, None);
+}
+
+#[test]
+fn test_matches_bincode_derive() {
+    let ping = Ping {
+        nonce: 7,
+        note: "seven".into(),
+    };
+    let reference = reference::Ping {
+        nonce: 7,
+        note: "seven".into(),
+    };
+    assert_eq!(encode(&ping), encode(&reference));
+
+    assert_eq!(
+        encode(&Command::Start { delay: 300 }),
+        encode(&reference::Command::Start { delay: 300 })
+    );
+    assert_eq!(
+        encode(&Command::Rename("x".into())),
+        encode(&reference::Command::Rename("x".into()))
+    );
+    assert_eq!(encode(&Command::Stop), encode(&reference::Command::Stop));
+
+    let config = bincode::config::standard().with_big_endian();
+    let err = bincode::decode_from_slice::<Command, _>(&[3], config).unwrap_err();
+    assert!(matches!(
+        err,
+        bincode::error::DecodeError::UnexpectedVariant { found: 3, .. }
+    ));
+}
+
+#[tokio::test]
+async fn test_roundtrip() {
+    let (client, server) = tokio::io::duplex(1024);
+    let (client_read, client_write) = tokio::io::split(client);
+    let (server_read, server_write) = tokio::io::split(server);
+
+    let mut client = Transport::new(client_read, client_write);
+    let mut server = Transport::new(server_read, server_write);
+
+    let ping = Ping {
+        nonce: 42,
+        note: "derived".into(),
+    };
+    let header = Header::new(Ping::ID.unwrap(), 1, MessageFlags::NONE, 0, 1)
+        .to_bytes::<StandardHeaderParser>();
+
+    client.write_message(ping.to_frame(header)).await.unwrap();
+    client
+        .write_message(Command::Rename("b".into()).to_frame(header))
+        .await
+        .unwrap();
+    client
+        .write_message(Pair(1u16, 2u16).to_frame(header))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        server.read_message::<Ping>().await.unwrap(),
+        Ping {
+            nonce: 42,
+            note: "derived".into(),
+        }
+    );
+    assert_eq!(
+        server.read_message::<Command>().await.unwrap(),
+        Command::Rename("b".into())
+    );
+    assert_eq!(
+        server.read_message::<Pair<u16>>().await.unwrap(),
+        Pair(1, 2)
+    );
+}
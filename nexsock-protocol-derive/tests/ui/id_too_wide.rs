@@ -0,0 +1,9 @@
+use nexsock_protocol_core::traits::MessageBody;
+
+#[derive(MessageBody)]
+#[message(id = 64)]
+struct Ping {
+    nonce: u64,
+}
+
+fn main() {}
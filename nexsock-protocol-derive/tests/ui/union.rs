@@ -0,0 +1,9 @@
+use nexsock_protocol_core::traits::MessageBody;
+
+#[derive(MessageBody)]
+union Raw {
+    int: u32,
+    float: f32,
+}
+
+fn main() {}
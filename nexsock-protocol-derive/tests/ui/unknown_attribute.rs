@@ -0,0 +1,9 @@
+use nexsock_protocol_core::traits::MessageBody;
+
+#[derive(MessageBody)]
+#[message(name = "ping")]
+struct Ping {
+    nonce: u64,
+}
+
+fn main() {}